@@ -0,0 +1,625 @@
+use crate::config::Config;
+use crate::cursor::cursor_position;
+use crate::error::ScreenError;
+use crate::metadata::Provenance;
+use crate::normalize::{normalize, Normalized};
+use crate::output::{claim_path, save_atomically};
+use crate::poe::active_monitor_number_from_poe_config;
+use crate::region::Region;
+use crate::screens::{all_screens, list_screens, ScreenInfo, ScreenSelector};
+use crate::sidecar::{sidecar_path, write_sidecar};
+use crate::tooltip::{crop_to_title_bar, find_title_bar, TitleTemplates};
+use crate::window::poe_window;
+use chrono::{DateTime, Local};
+use image::{imageops, ImageError, RgbaImage};
+use log::{debug, info, warn};
+use screenshots::Screen;
+use serde::Serialize;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Turn the configured screen into what a [`Capturer`] captures.
+///
+/// `Cursor` and `All` are resolved at capture time, everything else picks
+/// one screen now, see [`resolve_screen_id`].
+pub fn resolve_target(
+    screen: &ScreenSelector,
+    poe_config_path: Option<&Path>,
+) -> Result<CaptureTarget, ScreenError> {
+    match screen {
+        ScreenSelector::Cursor => Ok(CaptureTarget::Cursor),
+        ScreenSelector::All => Ok(CaptureTarget::All),
+        selector => resolve_screen(selector, poe_config_path)
+            .map(|(screen_id, chosen_by)| CaptureTarget::Screen(screen_id, chosen_by)),
+    }
+}
+
+/// Turn the configured screen into an index into `Screen::all()`.
+///
+/// `Auto` detects the monitor PoE runs on from `poe_config_path`, falling
+/// back to the first screen. `Cursor` picks the screen under the mouse
+/// pointer right now. Display ids, names and points are looked up in the
+/// current screens.
+pub fn resolve_screen_id(
+    screen: &ScreenSelector,
+    poe_config_path: Option<&Path>,
+) -> Result<usize, ScreenError> {
+    resolve_screen(screen, poe_config_path).map(|(screen_id, _)| screen_id)
+}
+
+fn resolve_screen(
+    screen: &ScreenSelector,
+    poe_config_path: Option<&Path>,
+) -> Result<(usize, ScreenChoice), ScreenError> {
+    match screen {
+        ScreenSelector::Auto => {}
+        ScreenSelector::Index(screen_id) => return Ok((*screen_id, ScreenChoice::Index)),
+        ScreenSelector::Cursor => {
+            let (x, y) = cursor_position()?;
+            let (screen_id, _) = resolve_screen(&ScreenSelector::ContainsPoint(x, y), None)?;
+
+            return Ok((screen_id, ScreenChoice::Cursor));
+        }
+        selector => {
+            let screens = list_screens(None)?;
+
+            return match selector.find(&screens) {
+                Some(info) => Ok((info.index, ScreenChoice::Identity)),
+                None => Err(ScreenError::NoMatchingScreen {
+                    selector: selector.clone(),
+                    screens,
+                }),
+            };
+        }
+    }
+
+    let detected = match poe_config_path {
+        Some(path) => active_monitor_number_from_poe_config(path)?,
+        None => None,
+    };
+
+    match detected {
+        Some(val) => Ok((val, ScreenChoice::PoeConfig)),
+        None => {
+            warn!("Couldn't auto-detect PoE screen, defaulting to 0");
+            Ok((0, ScreenChoice::Fallback))
+        }
+    }
+}
+
+/// Get the screen with index `screen_id` in `Screen::all()`.
+pub fn screen_by_id(screen_id: usize) -> Result<Screen, ScreenError> {
+    let screens = all_screens()?;
+
+    match screens.get(screen_id) {
+        Some(screen) => Ok(*screen),
+        None => Err(ScreenError::ScreenOutOfRange {
+            screen_id,
+            count: screens.len(),
+        }),
+    }
+}
+
+/// How the captured screen was chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScreenChoice {
+    /// `screen` is an index into `Screen::all()`.
+    Index,
+    /// Auto-detected from the PoE config.
+    PoeConfig,
+    /// Auto-detection failed and the first screen was used.
+    Fallback,
+    /// Matched by display id, name or point.
+    Identity,
+    /// The screen under the mouse pointer.
+    Cursor,
+    /// Every screen is captured.
+    All,
+}
+
+impl fmt::Display for ScreenChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ScreenChoice::Index => "index",
+            ScreenChoice::PoeConfig => "poe-config",
+            ScreenChoice::Fallback => "fallback",
+            ScreenChoice::Identity => "identity",
+            ScreenChoice::Cursor => "cursor",
+            ScreenChoice::All => "all",
+        })
+    }
+}
+
+/// Screens a [`Capturer`] takes screenshots of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureTarget {
+    /// One screen, by index into `Screen::all()`.
+    Screen(usize, ScreenChoice),
+    /// The screen under the mouse pointer at capture time.
+    Cursor,
+    /// Every screen, saved separately or stitched into one image.
+    All,
+}
+
+impl CaptureTarget {
+    pub fn chosen_by(&self) -> ScreenChoice {
+        match self {
+            CaptureTarget::Screen(_, chosen_by) => *chosen_by,
+            CaptureTarget::Cursor => ScreenChoice::Cursor,
+            CaptureTarget::All => ScreenChoice::All,
+        }
+    }
+}
+
+impl fmt::Display for CaptureTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CaptureTarget::Screen(screen_id, _) => write!(f, "screen {}", screen_id),
+            CaptureTarget::Cursor => write!(f, "the screen under the mouse pointer"),
+            CaptureTarget::All => write!(f, "all screens"),
+        }
+    }
+}
+
+/// A screenshot saved into the queue.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Capture {
+    /// Where the screenshot was saved.
+    pub path: PathBuf,
+    /// When the capture started.
+    pub time: DateTime<Local>,
+    /// The captured screen, `None` for stitched screenshots.
+    pub screen: Option<ScreenInfo>,
+    pub chosen_by: ScreenChoice,
+    /// Part of the screen that was captured, `None` for the whole screen.
+    pub region: Option<Region>,
+    /// Mouse pointer position in desktop coordinates, when capturing around it.
+    pub cursor: Option<(i32, i32)>,
+    /// Size of the captured image before normalizing and cropping.
+    pub original_size: (u32, u32),
+    /// Size of the saved image.
+    pub size: (u32, u32),
+    /// Factor the image was scaled by when normalizing, 1 otherwise.
+    pub scale: f32,
+    /// How long grabbing the screen took.
+    pub capture_time: Duration,
+    /// How long encoding and writing the image took.
+    pub encode_time: Duration,
+}
+
+/// One captured image on its way into the queue.
+struct Shot {
+    screen: Option<ScreenInfo>,
+    region: Option<Region>,
+    normalized: Normalized,
+    capture_time: Duration,
+}
+
+/// Takes screenshots into the queue.
+///
+/// Everything that doesn't change between captures (the screens, the
+/// templates) is prepared once, so a long-running process can reuse it.
+pub struct Capturer {
+    target: CaptureTarget,
+    /// Screens of a fixed target, empty for `Cursor`.
+    screens: Vec<(ScreenInfo, Screen)>,
+    queue_dir: PathBuf,
+    config: Config,
+    templates: Option<TitleTemplates>,
+}
+
+impl Capturer {
+    pub fn new(
+        target: CaptureTarget,
+        queue_dir: PathBuf,
+        config: Config,
+    ) -> Result<Self, ScreenError> {
+        let target = match target {
+            CaptureTarget::All if config.cursor_window.is_some() => {
+                info!("Capturing around the mouse pointer, only the screen under it is used");
+                CaptureTarget::Cursor
+            }
+            target => target,
+        };
+
+        if config.poe_region && (config.region.is_some() || config.cursor_window.is_some()) {
+            warn!("poe_region has no effect with region or cursor_window");
+        }
+
+        let screens = match target {
+            CaptureTarget::Screen(screen_id, chosen_by) => {
+                let screen = screen_by_id(screen_id)?;
+                let poe_detected = chosen_by == ScreenChoice::PoeConfig;
+
+                vec![(
+                    ScreenInfo::new(screen_id, &screen.display_info, poe_detected),
+                    screen,
+                )]
+            }
+            CaptureTarget::Cursor => Vec::new(),
+            CaptureTarget::All => all_screens()?
+                .into_iter()
+                .enumerate()
+                .map(|(index, screen)| {
+                    (ScreenInfo::new(index, &screen.display_info, false), screen)
+                })
+                .collect(),
+        };
+
+        for (_, screen) in &screens {
+            check_region(config.region, screen)?;
+        }
+
+        let templates = if config.crop || config.validate {
+            Some(TitleTemplates::new(config.templates_dir.as_deref())?)
+        } else {
+            None
+        };
+
+        Ok(Capturer {
+            target,
+            screens,
+            queue_dir,
+            config,
+            templates,
+        })
+    }
+
+    pub fn target(&self) -> CaptureTarget {
+        self.target
+    }
+
+    /// The screen that is captured, if it's always the same one.
+    pub fn screen_info(&self) -> Option<&ScreenInfo> {
+        match (self.target, self.screens.as_slice()) {
+            (CaptureTarget::Screen(..), [(info, _)]) => Some(info),
+            _ => None,
+        }
+    }
+
+    /// Capture the screens and save them into the queue.
+    ///
+    /// Screens without a unique item tooltip are skipped when validating,
+    /// that's only an error if none of them had one.
+    pub fn capture(&self) -> Result<Vec<Capture>, ScreenError> {
+        let config = &self.config;
+        let time = Local::now();
+        let cursor = if self.target == CaptureTarget::Cursor || config.cursor_window.is_some() {
+            Some(cursor_position()?)
+        } else if config.sidecar {
+            // Only recorded, so the capture doesn't depend on it
+            cursor_position()
+                .map_err(|err| debug!("Not recording the mouse position: {}", err))
+                .ok()
+        } else {
+            None
+        };
+
+        let screens = match (self.target, cursor) {
+            (CaptureTarget::Cursor, Some((x, y))) => {
+                let (screen_id, _) = resolve_screen(&ScreenSelector::ContainsPoint(x, y), None)?;
+                let screen = screen_by_id(screen_id)?;
+                check_region(config.region, &screen)?;
+
+                vec![(
+                    ScreenInfo::new(screen_id, &screen.display_info, false),
+                    screen,
+                )]
+            }
+            _ => self.screens.clone(),
+        };
+
+        // Looked up every time, the window can be moved between captures
+        let window =
+            if config.poe_region && config.region.is_none() && config.cursor_window.is_none() {
+                let window = poe_window()?;
+
+                if window.is_none() {
+                    warn!("PoE's window wasn't found, capturing the whole screen");
+                }
+
+                window
+            } else {
+                None
+            };
+
+        // Make screenshots
+        let mut images = Vec::new();
+
+        for (info, screen) in screens {
+            let region = match (config.cursor_window, cursor, window) {
+                (Some(size), Some((x, y)), _) => Some(Region::around(
+                    (x - info.x, y - info.y),
+                    size,
+                    info.width,
+                    info.height,
+                )),
+                (_, _, Some(window)) => window.region_on(&info),
+                _ => config.region,
+            };
+
+            let started = Instant::now();
+            let image = match region {
+                Some(region) => screen.capture_area(
+                    region.x as i32,
+                    region.y as i32,
+                    region.width,
+                    region.height,
+                ),
+                None => screen.capture(),
+            }
+            .map_err(|err| ScreenError::Capture(err.to_string()))?;
+
+            images.push((info, region, image, started.elapsed()));
+        }
+
+        // Screens can't be normalized separately without breaking the layout
+        if self.target == CaptureTarget::All && config.stitch {
+            let capture_time = images.iter().map(|(_, _, _, elapsed)| *elapsed).sum();
+            let parts = images
+                .into_iter()
+                .map(|(info, region, image, _)| {
+                    // Where the image is on the virtual desktop
+                    let (x, y) = region.map_or((0, 0), |region| (region.x as i32, region.y as i32));
+
+                    ((info.x + x, info.y + y), image)
+                })
+                .collect::<Vec<_>>();
+            let shot = Shot {
+                screen: None,
+                region: None,
+                normalized: unscaled(stitch(&parts)),
+                capture_time,
+            };
+
+            return self.save(shot, &time, cursor).map(|capture| vec![capture]);
+        }
+
+        let mut captures = Vec::new();
+        let mut rejected = None;
+
+        for (info, region, image, capture_time) in images {
+            let normalized = if config.normalize {
+                let part_height = region.map_or(info.height, |region| region.height);
+                normalize(image, info.height, part_height)
+            } else {
+                unscaled(image)
+            };
+            let shot = Shot {
+                screen: Some(info),
+                region,
+                normalized,
+                capture_time,
+            };
+
+            match self.save(shot, &time, cursor) {
+                Ok(capture) => captures.push(capture),
+                Err(err @ ScreenError::NoTooltip { .. }) => rejected = Some(err),
+                Err(err) => return Err(err),
+            }
+        }
+
+        match rejected {
+            Some(err) if captures.is_empty() => Err(err),
+            _ => Ok(captures),
+        }
+    }
+
+    /// Look for the tooltip in the image and save it into the queue.
+    fn save(
+        &self,
+        shot: Shot,
+        time: &DateTime<Local>,
+        cursor: Option<(i32, i32)>,
+    ) -> Result<Capture, ScreenError> {
+        let config = &self.config;
+        let Normalized {
+            mut image,
+            original_size,
+            scale,
+        } = shot.normalized;
+        let screen = shot
+            .screen
+            .as_ref()
+            .map_or("all".to_string(), |info| info.index.to_string());
+        let claimed = claim_path(
+            &self.queue_dir,
+            &config.filename,
+            time,
+            &screen,
+            config.format,
+        )?;
+        let text = Provenance {
+            time,
+            screen: shot.screen.as_ref(),
+            chosen_by: self.target.chosen_by(),
+            resolution: original_size,
+            session: config.session.as_deref(),
+        }
+        .text_chunks();
+
+        if let Some(templates) = &self.templates {
+            match find_title_bar(&image, templates, config.threshold) {
+                Some(title_bar) if config.crop => {
+                    image = crop_to_title_bar(&image, &title_bar, config.crop_margin)
+                }
+                Some(_) => {}
+                None if config.validate => {
+                    return Err(reject(&image, claimed.path(), config, &text))
+                }
+                None => warn!("No unique item tooltip found, saving the full screenshot"),
+            }
+        }
+
+        let encode_time = claimed.encode(&image, config.format, config.png_compression, &text)?;
+        let capture = Capture {
+            path: claimed.path().to_path_buf(),
+            time: *time,
+            screen: shot.screen,
+            chosen_by: self.target.chosen_by(),
+            region: shot.region,
+            cursor,
+            original_size,
+            size: image.dimensions(),
+            scale,
+            capture_time: shot.capture_time,
+            encode_time,
+        };
+
+        // Written before the screenshot appears in the queue, so the matcher
+        // always finds it when it moves the screenshot along
+        if config.sidecar {
+            write_sidecar(&capture, &config.screen, config.session.as_deref())?;
+        }
+
+        if let Err(err) = claimed.publish() {
+            if config.sidecar {
+                let _ = fs::remove_file(sidecar_path(&capture.path));
+            }
+            return Err(err);
+        }
+
+        debug!(
+            "Saved {}, {}x{}px, captured in {} ms, encoded in {} ms",
+            capture.path.display(),
+            capture.size.0,
+            capture.size.1,
+            capture.capture_time.as_millis(),
+            capture.encode_time.as_millis()
+        );
+
+        Ok(capture)
+    }
+}
+
+/// Capture `target` and save the screenshots into `queue_dir`.
+pub fn capture_to_queue<P>(
+    target: CaptureTarget,
+    queue_dir: P,
+    config: &Config,
+) -> Result<Vec<Capture>, ScreenError>
+where
+    P: AsRef<Path>,
+{
+    Capturer::new(target, queue_dir.as_ref().to_path_buf(), config.clone())?.capture()
+}
+
+fn unscaled(image: RgbaImage) -> Normalized {
+    Normalized {
+        original_size: image.dimensions(),
+        image,
+        scale: 1.0,
+    }
+}
+
+fn check_region(region: Option<Region>, screen: &Screen) -> Result<(), ScreenError> {
+    let info = screen.display_info;
+
+    match region {
+        Some(region) if !region.fits(info.width, info.height) => {
+            Err(ScreenError::RegionOutOfBounds {
+                region,
+                width: info.width,
+                height: info.height,
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Put screenshots together at their positions on the virtual desktop.
+///
+/// Gaps between screens of different sizes stay transparent.
+fn stitch(parts: &[((i32, i32), RgbaImage)]) -> RgbaImage {
+    let left = parts.iter().map(|((x, _), _)| *x).min().unwrap_or(0);
+    let top = parts.iter().map(|((_, y), _)| *y).min().unwrap_or(0);
+    let right = parts
+        .iter()
+        .map(|((x, _), image)| *x + image.width() as i32)
+        .max()
+        .unwrap_or(0);
+    let bottom = parts
+        .iter()
+        .map(|((_, y), image)| *y + image.height() as i32)
+        .max()
+        .unwrap_or(0);
+
+    let mut stitched = RgbaImage::new((right - left) as u32, (bottom - top) as u32);
+
+    for ((x, y), image) in parts {
+        imageops::replace(
+            &mut stitched,
+            image,
+            i64::from(x - left),
+            i64::from(y - top),
+        );
+    }
+
+    stitched
+}
+
+/// Handle a screenshot without a tooltip, saving it to the `rejected`
+/// directory next to the queue if enabled.
+fn reject(
+    image: &RgbaImage,
+    image_path: &Path,
+    config: &Config,
+    text: &[(String, String)],
+) -> ScreenError {
+    if !config.save_rejected {
+        return ScreenError::NoTooltip { rejected: None };
+    }
+
+    let filename = image_path.file_name().unwrap_or_default();
+    let queue_dir = image_path.parent().unwrap_or(Path::new("."));
+    let rejected_dir = queue_dir.with_file_name("rejected");
+
+    if let Err(err) = fs::create_dir_all(&rejected_dir) {
+        return ScreenError::Write {
+            path: rejected_dir,
+            source: ImageError::IoError(err),
+        };
+    }
+
+    let rejected_path = rejected_dir.join(filename);
+
+    match save_atomically(
+        image,
+        &rejected_path,
+        config.format,
+        config.png_compression,
+        text,
+    ) {
+        Ok(_) => ScreenError::NoTooltip {
+            rejected: Some(rejected_path),
+        },
+        Err(err) => err,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn stitch_places_screens_on_the_virtual_desktop() {
+        let red = Rgba([255, 0, 0, 255]);
+        let blue = Rgba([0, 0, 255, 255]);
+        let parts = [
+            ((0, 1), RgbaImage::from_pixel(2, 2, red)),
+            ((-3, 0), RgbaImage::from_pixel(3, 1, blue)),
+        ];
+
+        let stitched = stitch(&parts);
+
+        assert_eq!(stitched.dimensions(), (5, 3));
+        assert_eq!(*stitched.get_pixel(0, 0), blue);
+        assert_eq!(*stitched.get_pixel(3, 1), red);
+        assert_eq!(*stitched.get_pixel(4, 2), red);
+        assert_eq!(stitched.get_pixel(3, 0).0[3], 0);
+    }
+}
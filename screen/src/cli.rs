@@ -0,0 +1,198 @@
+use clap::{Parser, Subcommand};
+use log::LevelFilter;
+use screen::daemon::DEFAULT_SOCKET;
+use screen::logging::parse_level;
+use screen::{OutputFormat, PngCompression, Region, ScreenSelector, Size};
+use std::path::PathBuf;
+
+/// Take a screenshot for the unique matcher.
+///
+/// Flags override the `UM_*` environment variables, which override the
+/// values from `config.ini`, e.g. `UM_SCREEN` for `screen`.
+#[derive(Debug, Parser)]
+#[command(version)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Screen to capture: index, -1 to auto-detect the monitor PoE runs on,
+    /// cursor, all, id:<id>, name:<name> or point:<x>,<y>
+    #[arg(long, global = true, allow_negative_numbers = true)]
+    pub screen: Option<ScreenSelector>,
+
+    /// Path to config.ini [env: UM_CONFIG] [default: ./config.ini]
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Directory to save the screenshot into [env: UM_OUTPUT_DIR] [default: ./data/queue]
+    #[arg(long, global = true)]
+    pub output_dir: Option<PathBuf>,
+
+    /// Directory to write screen.log into [env: UM_LOG_DIR]
+    /// [default: data/logs next to the config, or logs in the output directory]
+    #[arg(long, global = true)]
+    pub log_dir: Option<PathBuf>,
+
+    /// Use the settings of the [profile.<name>] section of config.ini [env: UM_PROFILE]
+    #[arg(long, global = true)]
+    pub profile: Option<String>,
+
+    /// Path to PoE's production_Config.ini [env: UM_POE_CONFIG]
+    #[arg(long, global = true)]
+    pub poe_config: Option<PathBuf>,
+
+    /// Image format of the screenshot (png, webp-lossless, bmp)
+    #[arg(long, global = true)]
+    pub format: Option<OutputFormat>,
+
+    /// Compression level of PNG screenshots (fast, default, best)
+    #[arg(long, global = true)]
+    pub png_compression: Option<PngCompression>,
+
+    /// Capture only this part of the screen, as x,y,width,height
+    #[arg(long, global = true, conflicts_with = "cursor_window")]
+    pub region: Option<Region>,
+
+    /// Capture a WIDTHxHEIGHT window around the mouse pointer
+    #[arg(long, global = true)]
+    pub cursor_window: Option<Size>,
+
+    /// Capture only PoE's window
+    #[arg(long, global = true)]
+    pub poe_region: bool,
+
+    /// With --screen all, save one image of the whole desktop
+    #[arg(long, global = true)]
+    pub stitch: bool,
+
+    /// Rescale the screenshot to the 1920x1080 geometry the matcher expects
+    #[arg(long, global = true)]
+    pub normalize: bool,
+
+    /// Save a JSON file with capture metadata next to the screenshot
+    #[arg(long, global = true)]
+    pub sidecar: bool,
+
+    /// Tag recorded in the screenshot metadata, e.g. the league
+    #[arg(long, global = true)]
+    pub session: Option<String>,
+
+    /// Crop the screenshot to the unique item tooltip
+    #[arg(long, global = true)]
+    pub crop: bool,
+
+    /// Reject the screenshot if it contains no unique item tooltip
+    #[arg(long, global = true)]
+    pub validate: bool,
+
+    /// Maximum score of a tooltip decoration match (0 to 1, lower is stricter)
+    #[arg(long, global = true, value_parser = parse_threshold)]
+    pub threshold: Option<f32>,
+
+    /// Lowest level written to data/logs/screen.log (error, warning, info, debug, trace)
+    #[arg(long, global = true, value_parser = parse_level)]
+    pub log_level: Option<LevelFilter>,
+
+    /// List the available screens and exit
+    #[arg(long)]
+    pub list_screens: bool,
+
+    /// Print the screen list as JSON
+    #[arg(long, requires = "list_screens")]
+    pub json: bool,
+
+    /// Resolve the screen and output path without capturing anything
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+impl Cli {
+    /// Settings given as flags, as `config.ini` keys and values.
+    pub fn overrides(&self) -> Vec<(&'static str, String)> {
+        let mut overrides = Vec::new();
+        let mut set = |key, value: Option<String>| {
+            if let Some(value) = value {
+                overrides.push((key, value));
+            }
+        };
+        let flag = |enabled: bool| enabled.then(|| "true".to_string());
+
+        set("screen", self.screen.as_ref().map(ToString::to_string));
+        set("format", self.format.map(|format| format.to_string()));
+        set(
+            "png_compression",
+            self.png_compression
+                .map(|compression| compression.to_string()),
+        );
+        set("region", self.region.map(|region| region.to_string()));
+        set(
+            "cursor_window",
+            self.cursor_window.map(|size| size.to_string()),
+        );
+        set("poe_region", flag(self.poe_region));
+        set("stitch", flag(self.stitch));
+        set("normalize", flag(self.normalize));
+        set("sidecar", flag(self.sidecar));
+        set("session", self.session.clone());
+        set("crop", flag(self.crop));
+        set("validate", flag(self.validate));
+        set(
+            "threshold",
+            self.threshold.map(|threshold| threshold.to_string()),
+        );
+        set("log_level", self.log_level.map(|level| level.to_string()));
+
+        overrides
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Stay resident and capture whenever `screen trigger` asks for it
+    Daemon {
+        /// Name of the local socket (named pipe on Windows)
+        #[arg(long, default_value = DEFAULT_SOCKET)]
+        socket: String,
+    },
+    /// Inspect the configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Create the data directories, config.ini and hotkey scripts
+    Init {
+        /// Directory to set up [default: the working directory]
+        dir: Option<PathBuf>,
+
+        /// Overwrite config.ini and the hotkey scripts if they exist
+        #[arg(long)]
+        force: bool,
+    },
+    /// Print the capture metadata embedded in a PNG screenshot
+    Meta {
+        /// Screenshot to read
+        file: PathBuf,
+    },
+    /// Ask a running `screen daemon` to capture a screenshot
+    Trigger {
+        /// Name of the local socket (named pipe on Windows)
+        #[arg(long, default_value = DEFAULT_SOCKET)]
+        socket: String,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Validate config.ini and print the effective settings and where they come from
+    Check,
+}
+
+fn parse_threshold(value: &str) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(threshold) if (0.0..=1.0).contains(&threshold) => Ok(threshold),
+        _ => Err(format!(
+            "expected a number between 0 and 1, got '{}'",
+            value
+        )),
+    }
+}
@@ -0,0 +1,863 @@
+//! Loading the capturer settings.
+//!
+//! Settings are looked up in increasing order of precedence:
+//!
+//! 1. the defaults, see [`Config::default`]
+//! 2. the `[screenshot]` section of `config.ini`
+//! 3. the `[profile.<name>]` section of the profile selected with `--profile`
+//!    or `UM_PROFILE`, if any
+//! 4. `UM_<KEY>` environment variables, e.g. `UM_SCREEN=cursor`
+//! 5. command-line flags
+//!
+//! The paths `main` works with follow the same order, `--config`, `UM_CONFIG`
+//! or `./config.ini`, `--output-dir`, `UM_OUTPUT_DIR` or `./data/queue`,
+//! `--log-dir`, `UM_LOG_DIR` or `data/logs` next to the config (`logs` in the
+//! output directory if only that is given) and `--poe-config`,
+//! `UM_POE_CONFIG` or PoE's config in the documents directory.
+
+use crate::error::ScreenError;
+use crate::logging::parse_level;
+use crate::output::{FilenameTemplate, OutputFormat, PngCompression};
+use crate::region::{Region, Size};
+use crate::screens::ScreenSelector;
+use crate::tooltip::THRESHOLD_CONTROL;
+use ini::Ini;
+use log::{warn, LevelFilter};
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Settings from the `[screenshot]` section of `config.ini`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    /// Screen to capture, by index, display id, name or a point on it.
+    pub screen: ScreenSelector,
+    /// Image format of the saved screenshots.
+    pub format: OutputFormat,
+    /// Compression level when saving PNG.
+    pub png_compression: PngCompression,
+    /// Template for the screenshot filenames.
+    pub filename: FilenameTemplate,
+    /// Part of the screen to capture instead of the whole screen.
+    pub region: Option<Region>,
+    /// Capture a window of this size around the mouse pointer instead of the
+    /// whole screen.
+    pub cursor_window: Option<Size>,
+    /// Capture only the part of the screen PoE's window covers, looked up on
+    /// the desktop at every capture.
+    pub poe_region: bool,
+    /// Save one image of the whole virtual desktop for `screen = all`.
+    pub stitch: bool,
+    /// Rescale captures of other resolutions to the 1080p geometry.
+    pub normalize: bool,
+    /// Save a `<name>.json` sidecar with capture metadata next to each screenshot.
+    pub sidecar: bool,
+    /// Tag recorded in the metadata of every screenshot, e.g. the league.
+    pub session: Option<String>,
+    /// Crop the screenshot to the unique item tooltip.
+    pub crop: bool,
+    /// Pixels kept around the tooltip when cropping.
+    pub crop_margin: u32,
+    /// Reject screenshots without a unique item tooltip.
+    pub validate: bool,
+    /// Keep rejected screenshots in the `rejected` directory next to the queue.
+    pub save_rejected: bool,
+    /// Maximum score of a title bar decoration match.
+    pub threshold: f32,
+    /// Directory with title bar templates to use instead of the embedded ones.
+    pub templates_dir: Option<PathBuf>,
+    /// Lowest level of messages written to `data/logs/screen.log`.
+    pub log_level: LevelFilter,
+}
+
+impl Config {
+    /// Keys of the `[screenshot]` section, in the order of the fields.
+    pub const KEYS: [&'static str; 18] = [
+        "screen",
+        "format",
+        "png_compression",
+        "filename",
+        "region",
+        "cursor_window",
+        "poe_region",
+        "stitch",
+        "normalize",
+        "sidecar",
+        "session",
+        "crop",
+        "crop_margin",
+        "validate",
+        "save_rejected",
+        "threshold",
+        "templates_dir",
+        "log_level",
+    ];
+
+    /// Set `key` from a value as written in `config.ini`.
+    ///
+    /// An empty value unsets the optional settings. Relative paths are
+    /// resolved against `base_dir`.
+    pub fn set(&mut self, key: &str, value: &str, base_dir: &Path) -> Result<(), String> {
+        let value = value.trim();
+
+        match key {
+            "screen" => {
+                self.screen = value
+                    .parse()
+                    .map_err(|err| format!("invalid screen: {}", err))?
+            }
+            "format" => self.format = value.parse()?,
+            "png_compression" => self.png_compression = value.parse()?,
+            "filename" => self.filename = FilenameTemplate::new(value)?,
+            "region" => self.region = parse_optional(value)?,
+            "cursor_window" => self.cursor_window = parse_optional(value)?,
+            "poe_region" => self.poe_region = parse_flag(key, value)?,
+            "stitch" => self.stitch = parse_flag(key, value)?,
+            "normalize" => self.normalize = parse_flag(key, value)?,
+            "sidecar" => self.sidecar = parse_flag(key, value)?,
+            "session" => self.session = Some(value.to_string()).filter(|s| !s.is_empty()),
+            "crop" => self.crop = parse_flag(key, value)?,
+            "crop_margin" => self.crop_margin = parse_value(key, value)?,
+            "validate" => self.validate = parse_flag(key, value)?,
+            "save_rejected" => self.save_rejected = parse_flag(key, value)?,
+            "threshold" => self.threshold = parse_value(key, value)?,
+            "templates_dir" => {
+                self.templates_dir = Some(value)
+                    .filter(|dir| !dir.is_empty())
+                    .map(|dir| base_dir.join(dir))
+            }
+            "log_level" => self.log_level = parse_level(value)?,
+            _ => return Err(format!("unknown setting '{}'", key)),
+        }
+
+        Ok(())
+    }
+
+    /// Check the settings that have a limited range or depend on each other.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.region.is_some() && self.cursor_window.is_some() {
+            return Err("region and cursor_window can't be used together".to_string());
+        }
+
+        if !(0.0..=1.0).contains(&self.threshold) {
+            return Err(format!(
+                "threshold must be between 0 and 1, got {}",
+                self.threshold
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Every setting with its value as it would be written in `config.ini`.
+    pub fn entries(&self) -> Vec<(&'static str, String)> {
+        fn optional<T: ToString>(value: &Option<T>) -> String {
+            value.as_ref().map_or(String::new(), T::to_string)
+        }
+
+        let values = [
+            self.screen.to_string(),
+            self.format.to_string(),
+            self.png_compression.to_string(),
+            self.filename.to_string(),
+            optional(&self.region),
+            optional(&self.cursor_window),
+            self.poe_region.to_string(),
+            self.stitch.to_string(),
+            self.normalize.to_string(),
+            self.sidecar.to_string(),
+            optional(&self.session),
+            self.crop.to_string(),
+            self.crop_margin.to_string(),
+            self.validate.to_string(),
+            self.save_rejected.to_string(),
+            self.threshold.to_string(),
+            optional(&self.templates_dir.as_ref().map(|dir| dir.display())),
+            self.log_level.to_string().to_ascii_lowercase(),
+        ];
+
+        Self::KEYS.into_iter().zip(values).collect()
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            screen: ScreenSelector::default(),
+            format: OutputFormat::default(),
+            png_compression: PngCompression::default(),
+            filename: FilenameTemplate::default(),
+            region: None,
+            cursor_window: None,
+            poe_region: false,
+            stitch: false,
+            normalize: false,
+            sidecar: false,
+            session: None,
+            crop: false,
+            crop_margin: 20,
+            validate: false,
+            save_rejected: false,
+            threshold: THRESHOLD_CONTROL,
+            templates_dir: None,
+            log_level: LevelFilter::Debug,
+        }
+    }
+}
+
+/// Prefix of the sections with capture profiles, `[profile.<name>]`.
+pub const PROFILE_PREFIX: &str = "profile.";
+
+/// Where the value of a setting came from, in increasing precedence.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Source {
+    #[default]
+    Default,
+    /// The `[screenshot]` section of `config.ini`.
+    File,
+    /// The `[profile.<name>]` section of the selected profile.
+    Profile,
+    Env,
+    Cli,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Default => f.write_str("default"),
+            Source::File => f.write_str("file"),
+            Source::Profile => f.write_str("profile"),
+            Source::Env => f.write_str("environment"),
+            Source::Cli => f.write_str("command line"),
+        }
+    }
+}
+
+/// Where each setting of a [`Config`] came from, by key.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Sources(HashMap<String, Source>);
+
+impl Sources {
+    pub fn get(&self, key: &str) -> Source {
+        self.0.get(key).copied().unwrap_or_default()
+    }
+
+    pub fn set(&mut self, key: &str, source: Source) {
+        self.0.insert(key.to_string(), source);
+    }
+}
+
+/// `UM_*` environment variables, which override `config.ini`.
+#[derive(Debug, Clone, Default)]
+pub struct Environment(HashMap<String, String>);
+
+impl Environment {
+    pub const PREFIX: &'static str = "UM_";
+
+    /// Variables for paths and the profile rather than settings, without the prefix.
+    pub const OTHER: [&'static str; 5] =
+        ["CONFIG", "OUTPUT_DIR", "LOG_DIR", "POE_CONFIG", "PROFILE"];
+
+    /// The `UM_*` variables of this process.
+    pub fn from_process() -> Self {
+        Self::from_vars(env::vars_os().filter_map(|(name, value)| {
+            Some((name.into_string().ok()?, value.into_string().ok()?))
+        }))
+    }
+
+    /// The `UM_*` variables among `vars`.
+    pub fn from_vars<I>(vars: I) -> Self
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        Environment(
+            vars.into_iter()
+                .filter_map(|(name, value)| {
+                    Some((name.strip_prefix(Self::PREFIX)?.to_string(), value))
+                })
+                .collect(),
+        )
+    }
+
+    /// Name of the variable for a `config.ini` key, `screen` is `UM_SCREEN`.
+    pub fn var_name(key: &str) -> String {
+        format!("{}{}", Self::PREFIX, key.to_ascii_uppercase())
+    }
+
+    /// Value of `UM_<name>`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.get(name).map(String::as_str)
+    }
+
+    /// Path in `UM_<name>`, ignored if empty.
+    pub fn path(&self, name: &str) -> Option<PathBuf> {
+        self.get(name)
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+    }
+
+    /// Settings given as variables, as `config.ini` keys and values.
+    pub fn overrides(&self) -> Vec<(&'static str, String)> {
+        Config::KEYS
+            .into_iter()
+            .filter_map(|key| Some((key, self.get(&key.to_ascii_uppercase())?.to_string())))
+            .collect()
+    }
+
+    /// Names of the `UM_*` variables that don't override anything.
+    pub fn unknown(&self) -> Vec<String> {
+        let mut unknown: Vec<_> = self
+            .0
+            .keys()
+            .filter(|name| {
+                !Self::OTHER.contains(&name.as_str())
+                    && !Config::KEYS.contains(&name.to_ascii_lowercase().as_str())
+            })
+            .map(|name| format!("{}{}", Self::PREFIX, name))
+            .collect();
+        unknown.sort();
+
+        unknown
+    }
+}
+
+/// Pick a flag over an environment variable over a default, and tell which.
+pub fn layered<T, F>(cli: Option<T>, env: Option<T>, default: F) -> (T, Source)
+where
+    F: FnOnce() -> T,
+{
+    match (cli, env) {
+        (Some(value), _) => (value, Source::Cli),
+        (None, Some(value)) => (value, Source::Env),
+        (None, None) => (default(), Source::Default),
+    }
+}
+
+/// Load `config.ini`, falling back to the defaults if the file doesn't exist.
+pub fn load_config<P>(path: P) -> Result<Config, ScreenError>
+where
+    P: AsRef<Path>,
+{
+    load_config_sources(path, None).map(|(config, _)| config)
+}
+
+/// Load `config.ini` like [`load_config`], also telling which settings it set.
+///
+/// With a `profile`, the settings of its `[profile.<name>]` section are
+/// applied on top of `[screenshot]`. Unknown sections and keys are logged as
+/// warnings and otherwise ignored.
+pub fn load_config_sources<P>(
+    path: P,
+    profile: Option<&str>,
+) -> Result<(Config, Sources), ScreenError>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let config_error = |reason: String| ScreenError::Config {
+        path: path.to_path_buf(),
+        reason,
+    };
+
+    let mut config = Config::default();
+    let mut sources = Sources::default();
+
+    let ini_file = match Ini::load_from_file(path) {
+        Ok(ini_file) => ini_file,
+        Err(ini::Error::Io(err)) if err.kind() == io::ErrorKind::NotFound => {
+            return match profile {
+                Some(profile) => Err(config_error(format!(
+                    "profile '{}' doesn't exist, the file is missing",
+                    profile
+                ))),
+                None => Ok((config, sources)),
+            };
+        }
+        Err(err) => return Err(config_error(err.to_string())),
+    };
+
+    for (section, properties) in ini_file.iter() {
+        let Some(section) = section else {
+            for (key, _) in properties.iter() {
+                warn!(
+                    "Setting '{}' outside of [screenshot] in {} is ignored",
+                    key,
+                    path.display()
+                );
+            }
+            continue;
+        };
+
+        if section != "screenshot" && !section.starts_with(PROFILE_PREFIX) {
+            warn!("Unknown section [{}] in {}", section, path.display());
+            continue;
+        }
+
+        for (key, _) in properties.iter() {
+            if !Config::KEYS.contains(&key) {
+                warn!(
+                    "Unknown setting '{}' in [{}] of {}",
+                    key,
+                    section,
+                    path.display()
+                );
+            }
+        }
+    }
+
+    // Relative paths are relative to config.ini, so they don't depend on the working directory
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+    let known = |(key, _): &(&str, &str)| Config::KEYS.contains(key);
+
+    if let Some(properties) = ini_file.section(Some("screenshot")) {
+        for (key, value) in properties.iter().filter(known) {
+            config.set(key, value, base_dir).map_err(config_error)?;
+            sources.set(key, Source::File);
+        }
+    }
+
+    if let Some(profile) = profile {
+        let section = format!("{}{}", PROFILE_PREFIX, profile);
+        let properties = ini_file.section(Some(section.as_str())).ok_or_else(|| {
+            let profiles: Vec<_> = ini_file
+                .sections()
+                .flatten()
+                .filter_map(|section| section.strip_prefix(PROFILE_PREFIX))
+                .collect();
+
+            config_error(format!(
+                "profile '{}' has no [{}] section, available profiles: {}",
+                profile,
+                section,
+                if profiles.is_empty() {
+                    "none".to_string()
+                } else {
+                    profiles.join(", ")
+                }
+            ))
+        })?;
+
+        for (key, value) in properties.iter().filter(known) {
+            apply(
+                &mut config,
+                &mut sources,
+                key,
+                value,
+                Source::Profile,
+                base_dir,
+            )
+            .map_err(|err| config_error(format!("[{}]: {}", section, err)))?;
+        }
+    }
+
+    config.validate().map_err(config_error)?;
+
+    Ok((config, sources))
+}
+
+/// Load `config.ini` at `path` with `profile` and apply the overrides on top of it.
+///
+/// `cli` holds the flags as `config.ini` keys and values. Relative paths
+/// in the environment or the flags are resolved against `workdir`.
+pub fn resolve_config(
+    path: &Path,
+    profile: Option<&str>,
+    env: &Environment,
+    cli: &[(&'static str, String)],
+    workdir: &Path,
+) -> Result<(Config, Sources), ScreenError> {
+    let config_error = |reason: String| ScreenError::Config {
+        path: path.to_path_buf(),
+        reason,
+    };
+
+    for name in env.unknown() {
+        warn!("Unknown environment variable {} is ignored", name);
+    }
+
+    let (mut config, mut sources) = load_config_sources(path, profile)?;
+
+    for (key, value) in env.overrides() {
+        apply(&mut config, &mut sources, key, &value, Source::Env, workdir)
+            .map_err(|err| config_error(format!("{}: {}", Environment::var_name(key), err)))?;
+    }
+
+    for (key, value) in cli {
+        apply(&mut config, &mut sources, key, value, Source::Cli, workdir).map_err(config_error)?;
+    }
+
+    config.validate().map_err(config_error)?;
+
+    Ok((config, sources))
+}
+
+/// Set `key` and record where it came from.
+///
+/// Region and cursor window are exclusive, setting one unsets the other,
+/// so an override doesn't clash with the other one in `config.ini`.
+fn apply(
+    config: &mut Config,
+    sources: &mut Sources,
+    key: &str,
+    value: &str,
+    source: Source,
+    base_dir: &Path,
+) -> Result<(), String> {
+    config.set(key, value, base_dir)?;
+    sources.set(key, source);
+
+    if key == "region" && config.region.is_some() && config.cursor_window.is_some() {
+        config.cursor_window = None;
+        sources.set("cursor_window", source);
+    }
+
+    if key == "cursor_window" && config.cursor_window.is_some() && config.region.is_some() {
+        config.region = None;
+        sources.set("region", source);
+    }
+
+    Ok(())
+}
+
+fn parse_optional<T>(value: &str) -> Result<Option<T>, String>
+where
+    T: FromStr<Err = String>,
+{
+    if value.is_empty() {
+        return Ok(None);
+    }
+
+    value.parse().map(Some)
+}
+
+fn parse_value<T>(key: &str, value: &str) -> Result<T, String>
+where
+    T: FromStr,
+{
+    value
+        .parse::<T>()
+        .map_err(|_| format!("invalid value for {}: '{}'", key, value))
+}
+
+fn parse_flag(key: &str, value: &str) -> Result<bool, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Ok(true),
+        "false" | "no" | "off" | "0" => Ok(false),
+        _ => Err(format!("{} must be true or false, got '{}'", key, value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn write_config(contents: &str) -> (tempfile::TempDir, std::path::PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.ini");
+        fs::write(&path, contents).unwrap();
+
+        (dir, path)
+    }
+
+    #[test]
+    fn missing_file_uses_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+
+        assert_eq!(
+            load_config(dir.path().join("config.ini")).unwrap(),
+            Config::default()
+        );
+    }
+
+    #[test]
+    fn reads_screen_from_screenshot_section() {
+        let (_dir, path) = write_config("[screenshot]\nscreen = -1\n");
+
+        assert_eq!(load_config(&path).unwrap().screen, ScreenSelector::Auto);
+    }
+
+    #[test]
+    fn reads_screen_by_name() {
+        let (_dir, path) = write_config("[screenshot]\nscreen = name:HDMI-1\n");
+
+        assert_eq!(
+            load_config(&path).unwrap().screen,
+            ScreenSelector::Name("HDMI-1".to_string())
+        );
+    }
+
+    #[test]
+    fn reads_format_from_screenshot_section() {
+        let (_dir, path) = write_config("[screenshot]\nformat = bmp\n");
+
+        assert_eq!(load_config(&path).unwrap().format, OutputFormat::Bmp);
+    }
+
+    #[test]
+    fn reads_png_compression() {
+        let (_dir, path) =
+            write_config("[screenshot]\nformat = webp-lossless\npng_compression = best\n");
+        let config = load_config(&path).unwrap();
+
+        assert_eq!(config.format, OutputFormat::WebpLossless);
+        assert_eq!(config.png_compression, PngCompression::Best);
+    }
+
+    #[test]
+    fn reads_crop_settings() {
+        let (_dir, path) = write_config("[screenshot]\ncrop = yes\ncrop_margin = 5\n");
+        let config = load_config(&path).unwrap();
+
+        assert!(config.crop);
+        assert_eq!(config.crop_margin, 5);
+    }
+
+    #[test]
+    fn reads_region() {
+        let (_dir, path) = write_config("[screenshot]\nregion = 960,0,960,1080\n");
+
+        assert_eq!(
+            load_config(&path).unwrap().region,
+            Some(Region {
+                x: 960,
+                y: 0,
+                width: 960,
+                height: 1080
+            })
+        );
+    }
+
+    #[test]
+    fn region_and_cursor_window_are_exclusive() {
+        let (_dir, path) =
+            write_config("[screenshot]\nregion = 0,0,800,600\ncursor_window = 800x600\n");
+
+        assert!(matches!(
+            load_config(&path),
+            Err(ScreenError::Config { .. })
+        ));
+    }
+
+    #[test]
+    fn reads_all_screens_stitched() {
+        let (_dir, path) = write_config("[screenshot]\nscreen = all\nstitch = on\n");
+        let config = load_config(&path).unwrap();
+
+        assert_eq!(config.screen, ScreenSelector::All);
+        assert!(config.stitch);
+    }
+
+    #[test]
+    fn templates_dir_is_relative_to_config() {
+        let (dir, path) = write_config("[screenshot]\ntemplates_dir = templates\n");
+
+        assert_eq!(
+            load_config(&path).unwrap().templates_dir,
+            Some(dir.path().join("templates"))
+        );
+    }
+
+    #[test]
+    fn threshold_out_of_range_is_an_error() {
+        let (_dir, path) = write_config("[screenshot]\nvalidate = true\nthreshold = 2\n");
+
+        assert!(matches!(
+            load_config(&path),
+            Err(ScreenError::Config { .. })
+        ));
+    }
+
+    #[test]
+    fn reads_log_level() {
+        let (_dir, path) = write_config("[screenshot]\nlog_level = warning\n");
+
+        assert_eq!(load_config(&path).unwrap().log_level, LevelFilter::Warn);
+    }
+
+    #[test]
+    fn unknown_keys_are_ignored() {
+        let (_dir, path) =
+            write_config("stray = 1\n[screenshot]\nscren = 2\nformat = bmp\n[other]\nscreen = 3\n");
+        let (config, sources) = load_config_sources(&path, None).unwrap();
+
+        assert_eq!(config.screen, ScreenSelector::default());
+        assert_eq!(config.format, OutputFormat::Bmp);
+        assert_eq!(sources.get("format"), Source::File);
+        assert_eq!(sources.get("screen"), Source::Default);
+    }
+
+    #[test]
+    fn empty_value_unsets_optional_settings() {
+        let (_dir, path) = write_config("[screenshot]\nregion =\nsession =\n");
+        let (config, sources) = load_config_sources(&path, None).unwrap();
+
+        assert_eq!(config.region, None);
+        assert_eq!(config.session, None);
+        assert_eq!(sources.get("region"), Source::File);
+    }
+
+    #[test]
+    fn entries_round_trip() {
+        let mut config = Config {
+            screen: ScreenSelector::Name("HDMI-1".to_string()),
+            format: OutputFormat::WebpLossless,
+            cursor_window: Some(Size {
+                width: 800,
+                height: 600,
+            }),
+            session: Some("Affliction".to_string()),
+            threshold: 0.25,
+            templates_dir: Some(PathBuf::from("/opt/templates")),
+            log_level: LevelFilter::Warn,
+            ..Config::default()
+        };
+        config.filename = FilenameTemplate::new("{screen}-{seq}").unwrap();
+
+        let mut parsed = Config::default();
+
+        for (key, value) in config.entries() {
+            parsed.set(key, &value, Path::new("")).unwrap();
+        }
+
+        assert_eq!(parsed, config);
+    }
+
+    fn env(vars: &[(&str, &str)]) -> Environment {
+        Environment::from_vars(
+            vars.iter()
+                .map(|(name, value)| (name.to_string(), value.to_string())),
+        )
+    }
+
+    #[test]
+    fn precedence_is_cli_env_file_default() {
+        let (dir, path) = write_config("[screenshot]\nscreen = 1\nformat = bmp\ncrop = yes\n");
+        let env = env(&[
+            ("UM_SCREEN", "2"),
+            ("UM_FORMAT", "webp"),
+            ("PATH", "/usr/bin"),
+        ]);
+        let cli = [("screen", "3".to_string())];
+        let (config, sources) = resolve_config(&path, None, &env, &cli, dir.path()).unwrap();
+
+        assert_eq!(config.screen, ScreenSelector::Index(3));
+        assert_eq!(sources.get("screen"), Source::Cli);
+        assert_eq!(config.format, OutputFormat::WebpLossless);
+        assert_eq!(sources.get("format"), Source::Env);
+        assert!(config.crop);
+        assert_eq!(sources.get("crop"), Source::File);
+        assert_eq!(config.crop_margin, 20);
+        assert_eq!(sources.get("crop_margin"), Source::Default);
+    }
+
+    #[test]
+    fn env_region_replaces_cursor_window_from_file() {
+        let (dir, path) = write_config("[screenshot]\ncursor_window = 800x600\n");
+        let env = env(&[("UM_REGION", "0,0,800,600")]);
+        let (config, sources) = resolve_config(&path, None, &env, &[], dir.path()).unwrap();
+
+        assert!(config.region.is_some());
+        assert_eq!(config.cursor_window, None);
+        assert_eq!(sources.get("cursor_window"), Source::Env);
+    }
+
+    #[test]
+    fn invalid_env_value_names_the_variable() {
+        let (dir, path) = write_config("");
+        let env = env(&[("UM_THRESHOLD", "high")]);
+        let err = resolve_config(&path, None, &env, &[], dir.path()).unwrap_err();
+
+        assert!(err.to_string().contains("UM_THRESHOLD"));
+    }
+
+    #[test]
+    fn unknown_env_vars() {
+        let env = env(&[
+            ("UM_SCREEN", "0"),
+            ("UM_CONFIG", "other.ini"),
+            ("UM_SCREN", "0"),
+        ]);
+
+        assert_eq!(env.unknown(), vec!["UM_SCREN".to_string()]);
+        assert_eq!(env.path("CONFIG"), Some(PathBuf::from("other.ini")));
+    }
+
+    #[test]
+    fn layered_paths() {
+        let default = || PathBuf::from("config.ini");
+
+        assert_eq!(
+            layered(
+                Some(PathBuf::from("cli.ini")),
+                Some(PathBuf::from("env.ini")),
+                default
+            ),
+            (PathBuf::from("cli.ini"), Source::Cli)
+        );
+        assert_eq!(
+            layered(None, Some(PathBuf::from("env.ini")), default),
+            (PathBuf::from("env.ini"), Source::Env)
+        );
+        assert_eq!(layered(None, None, default), (default(), Source::Default));
+    }
+
+    const PROFILES: &str = "[screenshot]\nscreen = 1\ncursor_window = 800x600\n\
+         [profile.farm]\ncrop = yes\nvalidate = yes\n\
+         [profile.review]\nscreen = all\nregion = 0,0,1920,1080\nsidecar = on\n";
+
+    #[test]
+    fn profile_inherits_from_screenshot() {
+        let (_dir, path) = write_config(PROFILES);
+        let (config, sources) = load_config_sources(&path, Some("farm")).unwrap();
+
+        assert_eq!(config.screen, ScreenSelector::Index(1));
+        assert_eq!(sources.get("screen"), Source::File);
+        assert!(config.crop && config.validate);
+        assert_eq!(sources.get("crop"), Source::Profile);
+    }
+
+    #[test]
+    fn profile_region_replaces_cursor_window() {
+        let (_dir, path) = write_config(PROFILES);
+        let (config, sources) = load_config_sources(&path, Some("review")).unwrap();
+
+        assert_eq!(config.screen, ScreenSelector::All);
+        assert!(config.region.is_some());
+        assert_eq!(config.cursor_window, None);
+        assert_eq!(sources.get("cursor_window"), Source::Profile);
+    }
+
+    #[test]
+    fn env_overrides_profile() {
+        let (dir, path) = write_config(PROFILES);
+        let env = env(&[("UM_SIDECAR", "false")]);
+        let (config, sources) =
+            resolve_config(&path, Some("review"), &env, &[], dir.path()).unwrap();
+
+        assert!(!config.sidecar);
+        assert_eq!(sources.get("sidecar"), Source::Env);
+    }
+
+    #[test]
+    fn missing_profile_is_an_error() {
+        let (_dir, path) = write_config(PROFILES);
+        let err = load_config_sources(&path, Some("league")).unwrap_err();
+
+        assert!(matches!(err, ScreenError::Config { .. }));
+        assert!(err.to_string().contains("farm, review"));
+    }
+
+    #[test]
+    fn malformed_screen_is_an_error() {
+        let (_dir, path) = write_config("[screenshot]\nscreen = abc\n");
+
+        assert!(matches!(
+            load_config(&path),
+            Err(ScreenError::Config { .. })
+        ));
+    }
+}
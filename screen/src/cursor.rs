@@ -0,0 +1,86 @@
+//! Reading the mouse pointer position in desktop coordinates, the same ones
+//! `DisplayInfo` uses for the screen positions.
+
+use crate::error::ScreenError;
+
+/// Current position of the mouse pointer.
+#[cfg(target_os = "windows")]
+pub fn cursor_position() -> Result<(i32, i32), ScreenError> {
+    use windows::Win32::Foundation::POINT;
+    use windows::Win32::UI::WindowsAndMessaging::GetCursorPos;
+
+    let mut point = POINT::default();
+
+    // Safe, the pointer is valid for the duration of the call
+    unsafe { GetCursorPos(&mut point) }.map_err(|err| ScreenError::Cursor(err.to_string()))?;
+
+    Ok((point.x, point.y))
+}
+
+/// Current position of the mouse pointer.
+#[cfg(target_os = "linux")]
+pub fn cursor_position() -> Result<(i32, i32), ScreenError> {
+    let cursor_error = |err: String| ScreenError::Cursor(err);
+    let (conn, index) =
+        xcb::Connection::connect(None).map_err(|err| cursor_error(err.to_string()))?;
+    let root = conn
+        .get_setup()
+        .roots()
+        .nth(index as usize)
+        .ok_or_else(|| cursor_error("X server has no screens".to_string()))?
+        .root();
+
+    let cookie = conn.send_request(&xcb::x::QueryPointer { window: root });
+    let reply = conn
+        .wait_for_reply(cookie)
+        .map_err(|err| cursor_error(err.to_string()))?;
+
+    // X11 reports physical pixels, display-info divides the screen geometry
+    // by the Xft.dpi scale, which is the same for every screen
+    let scale_factor = crate::screens::all_screens()?[0].display_info.scale_factor;
+
+    Ok(to_logical(
+        (i32::from(reply.root_x()), i32::from(reply.root_y())),
+        scale_factor,
+    ))
+}
+
+/// Map a point in physical pixels to logical coordinates at `scale_factor`.
+#[cfg(target_os = "linux")]
+pub(crate) fn to_logical((x, y): (i32, i32), scale_factor: f32) -> (i32, i32) {
+    if scale_factor <= 0.0 {
+        return (x, y);
+    }
+
+    (
+        (x as f32 / scale_factor).floor() as i32,
+        (y as f32 / scale_factor).floor() as i32,
+    )
+}
+
+/// Current position of the mouse pointer.
+#[cfg(target_os = "macos")]
+pub fn cursor_position() -> Result<(i32, i32), ScreenError> {
+    use core_graphics::event::CGEvent;
+    use core_graphics::event_source::{CGEventSource, CGEventSourceStateID};
+
+    let cursor_error = || ScreenError::Cursor("cannot create a CoreGraphics event".to_string());
+    let source = CGEventSource::new(CGEventSourceStateID::CombinedSessionState)
+        .map_err(|_| cursor_error())?;
+    let location = CGEvent::new(source).map_err(|_| cursor_error())?.location();
+
+    Ok((location.x as i32, location.y as i32))
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn physical_pointer_to_logical_at_2x() {
+        assert_eq!(to_logical((3000, 1500), 2.0), (1500, 750));
+        assert_eq!(to_logical((3841, 1), 2.0), (1920, 0));
+        assert_eq!(to_logical((-20, 7), 2.0), (-10, 3));
+        assert_eq!(to_logical((1234, 567), 1.0), (1234, 567));
+    }
+}
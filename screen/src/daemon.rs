@@ -0,0 +1,288 @@
+//! Keeping a [`Capturer`] resident and triggering captures over a local socket.
+//!
+//! The socket is a Unix domain socket on Linux and macOS and a named pipe on
+//! Windows. The protocol is one line per request and response:
+//!
+//! - `capture` is answered by an `ok <path>` line per saved screenshot, or
+//!   `error <exit code> <message>`, and the connection is closed
+//!
+//! Clients have [`REQUEST_TIMEOUT`] to send their request.
+
+use crate::capture::Capturer;
+use crate::error::ScreenError;
+use interprocess::local_socket::prelude::*;
+use interprocess::local_socket::{
+    GenericFilePath, GenericNamespaced, ListenerOptions, Name, Stream,
+};
+use log::{error, info};
+use std::env;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Socket name used when none is given.
+pub const DEFAULT_SOCKET: &str = "unique-matcher-screen.sock";
+
+/// How long a client has to send its request line.
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Longest request line that is read, the rest is ignored.
+const MAX_REQUEST_LEN: usize = 1024;
+
+/// How often a named pipe is polled for the request, they have no read
+/// timeouts.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+fn socket_name(socket: &str) -> io::Result<Name<'_>> {
+    if GenericNamespaced::is_supported() {
+        socket.to_ns_name::<GenericNamespaced>()
+    } else {
+        // Fall back to a socket file in the temp dir, e.g. on macOS
+        env::temp_dir()
+            .join(socket)
+            .to_fs_name::<GenericFilePath>()
+            .map(|name| name.into_owned())
+    }
+}
+
+/// Serve capture requests on `socket` until the process is stopped.
+pub fn serve(capturer: &Capturer, socket: &str) -> Result<(), ScreenError> {
+    let name = socket_name(socket).map_err(ScreenError::Ipc)?;
+    let listener = ListenerOptions::new()
+        .name(name)
+        .create_sync()
+        .map_err(ScreenError::Ipc)?;
+
+    info!(
+        "Capturing {} on request, listening on {}",
+        capturer.target(),
+        socket
+    );
+
+    for conn in listener.incoming() {
+        let result = conn
+            .and_then(|conn| handle(conn, REQUEST_TIMEOUT, |request| respond(capturer, request)));
+
+        if let Err(err) = result {
+            error!("IPC connection failed: {}", err);
+        }
+    }
+
+    Ok(())
+}
+
+/// Read the request from `conn` and send back what `respond` makes of it.
+fn handle(
+    mut conn: Stream,
+    timeout: Duration,
+    respond: impl FnOnce(&str) -> String,
+) -> io::Result<()> {
+    // Requests are handled one at a time, a client that never finishes its
+    // request line mustn't block everyone else
+    let polled = match conn.set_recv_timeout(Some(timeout)) {
+        Ok(()) => false,
+        Err(err) if err.kind() == io::ErrorKind::Unsupported => {
+            conn.set_nonblocking(true)?;
+            true
+        }
+        Err(err) => return Err(err),
+    };
+
+    let request = read_request(&mut conn, Instant::now() + timeout)?;
+
+    if polled {
+        conn.set_nonblocking(false)?;
+    }
+
+    conn.write_all(respond(request.trim()).as_bytes())
+}
+
+/// Read the request line, failing with `TimedOut` once `deadline` passes.
+fn read_request(conn: &mut impl Read, deadline: Instant) -> io::Result<String> {
+    let mut request = Vec::new();
+    let mut buf = [0; MAX_REQUEST_LEN];
+
+    while request.len() < MAX_REQUEST_LEN && !request.contains(&b'\n') {
+        // Checked between reads too, so a client trickling bytes in doesn't
+        // get a new timeout with each of them
+        if Instant::now() >= deadline {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "no request received in time",
+            ));
+        }
+
+        match conn.read(&mut buf[..MAX_REQUEST_LEN - request.len()]) {
+            Ok(0) => break,
+            Ok(len) => request.extend_from_slice(&buf[..len]),
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => thread::sleep(POLL_INTERVAL),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+
+    let line = request
+        .split(|&byte| byte == b'\n')
+        .next()
+        .unwrap_or_default();
+
+    Ok(String::from_utf8_lossy(line).into_owned())
+}
+
+fn respond(capturer: &Capturer, request: &str) -> String {
+    match request {
+        "capture" => match capturer.capture() {
+            Ok(captures) => captures
+                .iter()
+                .map(|capture| {
+                    info!("Screenshot saved to: {}", capture.path.display());
+                    format!("ok {}\n", capture.path.display())
+                })
+                .collect(),
+            Err(err) => {
+                error!("{}", err);
+                format!("error {} {}\n", err.exit_code(), one_line(&err.to_string()))
+            }
+        },
+        other => format!("error 2 Unknown request '{}'\n", one_line(other)),
+    }
+}
+
+/// Join the lines of `message`, responses are one line each.
+fn one_line(message: &str) -> String {
+    message
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Ask the daemon listening on `socket` to capture a screenshot.
+///
+/// Return the paths of the saved screenshots.
+pub fn trigger(socket: &str) -> Result<Vec<PathBuf>, ScreenError> {
+    let name = socket_name(socket).map_err(ScreenError::Ipc)?;
+    let conn = Stream::connect(name).map_err(ScreenError::Ipc)?;
+    let mut conn = BufReader::new(conn);
+
+    conn.get_mut()
+        .write_all(b"capture\n")
+        .map_err(ScreenError::Ipc)?;
+
+    let mut image_paths = Vec::new();
+
+    for response in conn.lines() {
+        let response = response.map_err(ScreenError::Ipc)?;
+        image_paths.push(parse_response(response.trim_end())?);
+    }
+
+    if image_paths.is_empty() {
+        return Err(ScreenError::Ipc(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "no response from the capture daemon",
+        )));
+    }
+
+    Ok(image_paths)
+}
+
+fn parse_response(response: &str) -> Result<PathBuf, ScreenError> {
+    if let Some(path) = response.strip_prefix("ok ") {
+        return Ok(PathBuf::from(path));
+    }
+
+    let remote_error = response.strip_prefix("error ").and_then(|error| {
+        let (code, message) = error.split_once(' ')?;
+
+        Some(ScreenError::Remote {
+            code: code.parse().ok()?,
+            message: message.to_string(),
+        })
+    });
+
+    Err(remote_error.unwrap_or_else(|| {
+        ScreenError::Ipc(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unexpected response '{}'", response),
+        ))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ok_response() {
+        assert_eq!(
+            parse_response("ok data/queue/shot.png").unwrap(),
+            PathBuf::from("data/queue/shot.png")
+        );
+    }
+
+    #[test]
+    fn error_response_keeps_exit_code() {
+        let err = parse_response("error 11 No unique item tooltip found").unwrap_err();
+
+        assert_eq!(err.exit_code(), 11);
+        assert_eq!(err.to_string(), "No unique item tooltip found");
+    }
+
+    #[test]
+    fn error_messages_are_one_line() {
+        assert_eq!(
+            one_line("No screen matches 'HDMI-1', available screens:\n  Screen 0: display 1\n  Screen 1: display 2"),
+            "No screen matches 'HDMI-1', available screens: Screen 0: display 1 Screen 1: display 2"
+        );
+    }
+
+    #[test]
+    fn garbage_response() {
+        assert!(matches!(parse_response("hello"), Err(ScreenError::Ipc(_))));
+    }
+
+    /// Accept one connection on a fresh socket and handle it on a thread.
+    fn serve_once(
+        socket: &str,
+        timeout: Duration,
+    ) -> thread::JoinHandle<io::Result<Option<String>>> {
+        let listener = ListenerOptions::new()
+            .name(socket_name(socket).unwrap())
+            .create_sync()
+            .unwrap();
+
+        thread::spawn(move || {
+            let mut received = None;
+            handle(listener.accept()?, timeout, |request| {
+                received = Some(request.to_string());
+                "ok data/queue/shot.png\n".to_string()
+            })?;
+
+            Ok(received)
+        })
+    }
+
+    #[test]
+    fn handles_request_over_local_socket() {
+        let socket = format!("screen-test-{}-request.sock", std::process::id());
+        let server = serve_once(&socket, REQUEST_TIMEOUT);
+
+        assert_eq!(
+            trigger(&socket).unwrap(),
+            vec![PathBuf::from("data/queue/shot.png")]
+        );
+        assert_eq!(server.join().unwrap().unwrap().as_deref(), Some("capture"));
+    }
+
+    #[test]
+    fn silent_client_times_out() {
+        let socket = format!("screen-test-{}-silent.sock", std::process::id());
+        let server = serve_once(&socket, Duration::from_millis(100));
+        let _conn = Stream::connect(socket_name(&socket).unwrap()).unwrap();
+
+        let err = server.join().unwrap().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+}
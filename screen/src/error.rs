@@ -0,0 +1,240 @@
+use crate::region::Region;
+use crate::screens::{ScreenInfo, ScreenSelector};
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Everything that can go wrong while taking a screenshot.
+///
+/// Each variant maps to its own process exit code (see [`ScreenError::exit_code`]),
+/// so the script launching `screen` can tell the failures apart. Exit code 2
+/// is left for invalid command-line usage.
+#[derive(Debug)]
+pub enum ScreenError {
+    /// The current working directory can't be determined.
+    WorkingDir(io::Error),
+    /// `config.ini` exists but can't be read or contains an invalid value.
+    Config { path: PathBuf, reason: String },
+    /// PoE's `production_Config.ini` exists but can't be read or parsed.
+    PoeConfig { path: PathBuf, reason: String },
+    /// The system reports no displays at all.
+    NoDisplays,
+    /// The requested screen index doesn't exist.
+    ScreenOutOfRange { screen_id: usize, count: usize },
+    /// Listing the screens or capturing one of them failed.
+    Capture(String),
+    /// The screenshot couldn't be written to disk.
+    Write {
+        path: PathBuf,
+        source: image::ImageError,
+    },
+    /// No unique item tooltip was found and the capture was rejected,
+    /// `rejected` is where it was saved instead of the queue, if anywhere.
+    NoTooltip { rejected: Option<PathBuf> },
+    /// The title bar templates used for tooltip detection can't be loaded.
+    Templates {
+        path: PathBuf,
+        source: image::ImageError,
+    },
+    /// No screen matches the configured display id, name or point.
+    NoMatchingScreen {
+        selector: ScreenSelector,
+        screens: Vec<ScreenInfo>,
+    },
+    /// The configured region doesn't fit on the screen.
+    RegionOutOfBounds {
+        region: Region,
+        width: u32,
+        height: u32,
+    },
+    /// The mouse pointer position couldn't be read.
+    Cursor(String),
+    /// PoE's window couldn't be looked up.
+    Window(String),
+    /// Metadata couldn't be read from a screenshot.
+    Metadata { path: PathBuf, reason: String },
+    /// `screen init` couldn't create a directory or write a file.
+    Init { path: PathBuf, source: io::Error },
+    /// Talking to the capture daemon failed.
+    Ipc(io::Error),
+    /// The capture daemon reported an error, `code` is its exit code.
+    Remote { code: u8, message: String },
+}
+
+impl ScreenError {
+    /// Process exit code for this error, always non-zero.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            ScreenError::Config { .. } => 3,
+            ScreenError::PoeConfig { .. } => 4,
+            ScreenError::NoDisplays => 5,
+            ScreenError::ScreenOutOfRange { .. } => 6,
+            ScreenError::Capture(_) => 7,
+            ScreenError::Write { .. } => 8,
+            ScreenError::WorkingDir(_) => 9,
+            ScreenError::Templates { .. } => 10,
+            ScreenError::NoTooltip { .. } => 11,
+            ScreenError::Ipc(_) => 12,
+            ScreenError::RegionOutOfBounds { .. } => 13,
+            ScreenError::Cursor(_) => 14,
+            ScreenError::NoMatchingScreen { .. } => 15,
+            ScreenError::Metadata { .. } => 16,
+            ScreenError::Init { .. } => 17,
+            ScreenError::Window(_) => 18,
+            ScreenError::Remote { code, .. } => *code,
+        }
+    }
+}
+
+impl fmt::Display for ScreenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScreenError::WorkingDir(err) => {
+                write!(f, "Cannot determine the working directory: {}", err)
+            }
+            ScreenError::Config { path, reason } => {
+                write!(f, "Invalid config {}: {}", path.display(), reason)
+            }
+            ScreenError::PoeConfig { path, reason } => {
+                write!(f, "Invalid PoE config {}: {}", path.display(), reason)
+            }
+            ScreenError::NoDisplays => write!(f, "No displays found"),
+            ScreenError::ScreenOutOfRange { screen_id, count } => write!(
+                f,
+                "Cannot use screen {}, you only have {} screen(s) (IDs go from 0 to {})",
+                screen_id,
+                count,
+                count - 1,
+            ),
+            ScreenError::Capture(reason) => write!(f, "Cannot capture screen: {}", reason),
+            ScreenError::Write { path, source } => {
+                write!(f, "Cannot save {}: {}", path.display(), source)
+            }
+            ScreenError::NoTooltip { rejected: None } => {
+                write!(f, "No unique item tooltip found, screenshot discarded")
+            }
+            ScreenError::NoTooltip {
+                rejected: Some(path),
+            } => write!(
+                f,
+                "No unique item tooltip found, screenshot saved to: {}",
+                path.display()
+            ),
+            ScreenError::Templates { path, source } => {
+                write!(f, "Cannot load template {}: {}", path.display(), source)
+            }
+            ScreenError::NoMatchingScreen { selector, screens } => {
+                write!(f, "No screen matches '{}', available screens:", selector)?;
+
+                for info in screens {
+                    write!(f, "\n  {}", info)?;
+                }
+
+                Ok(())
+            }
+            ScreenError::RegionOutOfBounds {
+                region,
+                width,
+                height,
+            } => write!(
+                f,
+                "Region {} doesn't fit on the screen ({}x{}px)",
+                region, width, height
+            ),
+            ScreenError::Metadata { path, reason } => write!(
+                f,
+                "Cannot read metadata from {}: {}",
+                path.display(),
+                reason
+            ),
+            ScreenError::Cursor(err) => write!(f, "Cannot read the mouse position: {}", err),
+            ScreenError::Window(err) => write!(f, "Cannot find PoE's window: {}", err),
+            ScreenError::Init { path, source } => {
+                write!(f, "Cannot create {}: {}", path.display(), source)
+            }
+            ScreenError::Ipc(err) => write!(f, "Cannot reach the capture daemon: {}", err),
+            ScreenError::Remote { message, .. } => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for ScreenError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ScreenError::WorkingDir(err) => Some(err),
+            ScreenError::Write { source, .. } => Some(source),
+            ScreenError::Templates { source, .. } => Some(source),
+            ScreenError::Ipc(err) => Some(err),
+            ScreenError::Init { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Remote errors reuse the daemon's exit code, so they're not included
+    #[test]
+    fn exit_codes_are_distinct_and_non_zero() {
+        let errors = [
+            ScreenError::WorkingDir(io::Error::from(io::ErrorKind::NotFound)),
+            ScreenError::Config {
+                path: PathBuf::new(),
+                reason: String::new(),
+            },
+            ScreenError::PoeConfig {
+                path: PathBuf::new(),
+                reason: String::new(),
+            },
+            ScreenError::NoDisplays,
+            ScreenError::ScreenOutOfRange {
+                screen_id: 1,
+                count: 1,
+            },
+            ScreenError::Capture(String::new()),
+            ScreenError::Write {
+                path: PathBuf::new(),
+                source: image::ImageError::IoError(io::Error::from(io::ErrorKind::NotFound)),
+            },
+            ScreenError::Templates {
+                path: PathBuf::new(),
+                source: image::ImageError::IoError(io::Error::from(io::ErrorKind::NotFound)),
+            },
+            ScreenError::NoTooltip { rejected: None },
+            ScreenError::Ipc(io::Error::from(io::ErrorKind::NotFound)),
+            ScreenError::Cursor(String::new()),
+            ScreenError::Window(String::new()),
+            ScreenError::Metadata {
+                path: PathBuf::new(),
+                reason: String::new(),
+            },
+            ScreenError::Init {
+                path: PathBuf::new(),
+                source: io::Error::from(io::ErrorKind::NotFound),
+            },
+            ScreenError::NoMatchingScreen {
+                selector: ScreenSelector::Id(1),
+                screens: Vec::new(),
+            },
+            ScreenError::RegionOutOfBounds {
+                region: Region {
+                    x: 0,
+                    y: 0,
+                    width: 1,
+                    height: 1,
+                },
+                width: 0,
+                height: 0,
+            },
+        ];
+
+        let mut codes: Vec<u8> = errors.iter().map(ScreenError::exit_code).collect();
+        codes.sort();
+        codes.dedup();
+
+        assert_eq!(codes.len(), errors.len());
+        assert!(!codes.contains(&0));
+    }
+}
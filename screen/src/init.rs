@@ -0,0 +1,228 @@
+//! `screen init`, preparing a directory the way `main.py` does on the first
+//! start of the matcher, so `screen` can run before the GUI ever has.
+
+use crate::error::ScreenError;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Directories under `data` used by the capturer and the matcher.
+pub const DATA_DIRS: [&str; 4] = ["queue", "done", "errors", "logs"];
+
+/// One path [`init_workspace`] handled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InitEntry {
+    /// Relative to the workspace root.
+    pub path: PathBuf,
+    pub kind: EntryKind,
+    pub action: InitAction,
+}
+
+/// Whether an [`InitEntry`] is a directory or a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    Directory,
+    File,
+}
+
+/// What [`init_workspace`] did with one path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InitAction {
+    Created,
+    /// The path already existed and was left alone.
+    Kept,
+    /// The file already existed and `force` was set.
+    Overwritten,
+}
+
+impl fmt::Display for InitAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InitAction::Created => write!(f, "Created"),
+            InitAction::Kept => write!(f, "Kept"),
+            InitAction::Overwritten => write!(f, "Overwrote"),
+        }
+    }
+}
+
+/// Create the data directories, `config.ini` and the hotkey scripts in `root`.
+///
+/// `screen` is written into the new `config.ini` and `exe` is what the Linux
+/// hotkeys run. Existing files are only replaced if `force` is set, so
+/// running it again keeps the user's edits.
+///
+/// Return what was done with each path.
+pub fn init_workspace(
+    root: &Path,
+    screen: usize,
+    exe: &Path,
+    force: bool,
+) -> Result<Vec<InitEntry>, ScreenError> {
+    let mut entries = Vec::new();
+
+    for dir in DATA_DIRS {
+        let path = Path::new("data").join(dir);
+        let action = if root.join(&path).is_dir() {
+            InitAction::Kept
+        } else {
+            fs::create_dir_all(root.join(&path)).map_err(|source| ScreenError::Init {
+                path: root.join(&path),
+                source,
+            })?;
+            InitAction::Created
+        };
+
+        entries.push(InitEntry {
+            path,
+            kind: EntryKind::Directory,
+            action,
+        });
+    }
+
+    let root_str = root.display().to_string();
+    let files = [
+        ("config.ini", config_ini(screen)),
+        ("screenshot.ahk", ahk_script(&root_str)),
+        ("screenshot.xbindkeysrc", xbindkeys_snippet(root, exe)),
+        ("screenshot.sxhkdrc", sxhkd_snippet(root, exe)),
+    ];
+
+    for (name, contents) in files {
+        let path = root.join(name);
+        let action = match (path.exists(), force) {
+            (true, false) => InitAction::Kept,
+            (true, true) => InitAction::Overwritten,
+            (false, _) => InitAction::Created,
+        };
+
+        if action != InitAction::Kept {
+            fs::write(&path, contents).map_err(|source| ScreenError::Init {
+                path: path.clone(),
+                source,
+            })?;
+        }
+
+        entries.push(InitEntry {
+            path: PathBuf::from(name),
+            kind: EntryKind::File,
+            action,
+        });
+    }
+
+    Ok(entries)
+}
+
+fn config_ini(screen: usize) -> String {
+    format!(
+        "[screenshot]\n\
+         ; Screen to capture, run `screen --list-screens` to see them\n\
+         screen = {}\n",
+        screen
+    )
+}
+
+/// Same as `AHK_TEMPLATE` in `main.py`, with Windows line endings.
+fn ahk_script(root: &str) -> String {
+    format!(
+        "#s::\r\n{{\r\n    Run \"screen.exe\", \"{}\", \"Hide\"\r\n}}",
+        root
+    )
+}
+
+fn xbindkeys_snippet(root: &Path, exe: &Path) -> String {
+    format!(
+        "# Add to ~/.xbindkeysrc, Super+S takes a screenshot\n\
+         \"cd {} && {}\"\n    Mod4 + s\n",
+        shell_quote(root),
+        shell_quote(exe)
+    )
+}
+
+fn sxhkd_snippet(root: &Path, exe: &Path) -> String {
+    format!(
+        "# Add to ~/.config/sxhkd/sxhkdrc, Super+S takes a screenshot\n\
+         super + s\n    cd {} && {}\n",
+        shell_quote(root),
+        shell_quote(exe)
+    )
+}
+
+/// Quote a path for `sh`, keeping it intact when it contains spaces or quotes.
+fn shell_quote(path: &Path) -> String {
+    format!("'{}'", path.display().to_string().replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn creates_workspace() {
+        let dir = tempfile::tempdir().unwrap();
+        let entries = init_workspace(dir.path(), 1, Path::new("/opt/um/screen"), false).unwrap();
+
+        assert!(entries
+            .iter()
+            .all(|entry| entry.action == InitAction::Created));
+        assert_eq!(
+            entries
+                .iter()
+                .filter(|entry| entry.kind == EntryKind::Directory)
+                .count(),
+            DATA_DIRS.len()
+        );
+        assert!(dir.path().join("data").join("queue").is_dir());
+        assert!(dir.path().join("data").join("logs").is_dir());
+        assert!(fs::read_to_string(dir.path().join("config.ini"))
+            .unwrap()
+            .contains("screen = 1\n"));
+
+        let ahk = fs::read_to_string(dir.path().join("screenshot.ahk")).unwrap();
+        assert!(ahk.starts_with("#s::\r\n{\r\n"));
+        assert!(ahk.contains(&format!("\"{}\"", dir.path().display())));
+
+        let sxhkd = fs::read_to_string(dir.path().join("screenshot.sxhkdrc")).unwrap();
+        assert!(sxhkd.contains("super + s\n    cd '"));
+        assert!(sxhkd.ends_with("&& '/opt/um/screen'\n"));
+    }
+
+    #[test]
+    fn keeps_edits_unless_forced() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = dir.path().join("config.ini");
+        let exe = Path::new("screen");
+
+        init_workspace(dir.path(), 0, exe, false).unwrap();
+        fs::write(&config, "[screenshot]\nscreen = 2\n").unwrap();
+
+        let entries = init_workspace(dir.path(), 0, exe, false).unwrap();
+        assert!(entries.iter().all(|entry| entry.action == InitAction::Kept));
+        assert_eq!(
+            fs::read_to_string(&config).unwrap(),
+            "[screenshot]\nscreen = 2\n"
+        );
+
+        let entries = init_workspace(dir.path(), 0, exe, true).unwrap();
+        assert!(entries.contains(&InitEntry {
+            path: PathBuf::from("config.ini"),
+            kind: EntryKind::File,
+            action: InitAction::Overwritten
+        }));
+        assert!(entries.contains(&InitEntry {
+            path: Path::new("data").join("queue"),
+            kind: EntryKind::Directory,
+            action: InitAction::Kept
+        }));
+        assert!(fs::read_to_string(&config)
+            .unwrap()
+            .contains("screen = 0\n"));
+    }
+
+    #[test]
+    fn quotes_paths_for_the_shell() {
+        assert_eq!(
+            shell_quote(Path::new("/home/me/it's here")),
+            r"'/home/me/it'\''s here'"
+        );
+    }
+}
@@ -0,0 +1,47 @@
+//! Screenshot capturer for the unique matcher.
+//!
+//! The `screen` binary is a thin wrapper around this library, so the same
+//! config loading, PoE monitor detection and capture logic can be reused
+//! from other tools.
+
+pub mod capture;
+pub mod config;
+pub mod cursor;
+pub mod daemon;
+pub mod error;
+pub mod init;
+pub mod logging;
+pub mod matching;
+pub mod metadata;
+pub mod normalize;
+pub mod output;
+pub mod poe;
+pub mod region;
+pub mod screens;
+pub mod sidecar;
+pub mod tooltip;
+pub mod window;
+
+pub use capture::{
+    capture_to_queue, resolve_screen_id, resolve_target, screen_by_id, Capture, CaptureTarget,
+    Capturer, ScreenChoice,
+};
+pub use config::{
+    layered, load_config, load_config_sources, resolve_config, Config, Environment, Source, Sources,
+};
+pub use cursor::cursor_position;
+pub use error::ScreenError;
+pub use init::{init_workspace, EntryKind, InitAction, InitEntry};
+pub use logging::{init_logging, set_file_level};
+pub use matching::{match_template, Match, PreparedImage};
+pub use metadata::{read_metadata, Provenance};
+pub use normalize::{normalize, Normalized};
+pub use output::{
+    claim_path, save_atomically, unique_path, ClaimedPath, FilenameTemplate, OutputFormat,
+    PngCompression,
+};
+pub use poe::{active_monitor_number_from_poe_config, poe_config_path, PoeConfig, WindowMode};
+pub use region::{Region, Size};
+pub use screens::{all_screens, list_screens, ScreenInfo, ScreenSelector};
+pub use tooltip::{crop_to_title_bar, find_title_bar, TitleBar, TitleTemplates};
+pub use window::{poe_window, WindowRect};
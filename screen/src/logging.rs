@@ -0,0 +1,271 @@
+//! Logging to stderr and to `data/logs/screen.log`.
+//!
+//! The log file uses the same format as the matcher's `matcher.log` and is
+//! rotated like loguru's `rotation="10 MB"`: once full, it's renamed to
+//! `screen.<time>.log` and a new one is started.
+
+use chrono::{DateTime, Local};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+/// Size at which the log file is rotated, loguru's "10 MB".
+pub const ROTATION_SIZE: u64 = 10_000_000;
+
+/// Messages below this level are only written to the log file, not to stderr.
+const CONSOLE_LEVEL: LevelFilter = LevelFilter::Info;
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+/// Log file that is rotated once it would grow past `max_size`.
+///
+/// The file is opened on the first write, so commands that don't log
+/// anything don't create it.
+#[derive(Debug)]
+pub struct RotatingFile {
+    path: PathBuf,
+    max_size: u64,
+    file: Option<File>,
+    size: u64,
+}
+
+impl RotatingFile {
+    pub fn new(path: PathBuf, max_size: u64) -> Self {
+        RotatingFile {
+            path,
+            max_size,
+            file: None,
+            size: 0,
+        }
+    }
+
+    /// Append `line` and a newline, rotating the file first if it's full.
+    pub fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let len = line.len() as u64 + 1;
+
+        if self.file.is_none() {
+            self.open()?;
+        }
+
+        if self.size > 0 && self.size + len > self.max_size {
+            self.rotate()?;
+        }
+
+        let file = self.file.as_mut().expect("log file was opened");
+        writeln!(file, "{}", line)?;
+        self.size += len;
+
+        Ok(())
+    }
+
+    fn open(&mut self) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = file.metadata()?.len();
+        self.file = Some(file);
+
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        // Close the file first, Windows can't rename open files
+        self.file = None;
+        fs::rename(&self.path, rotated_path(&self.path, &Local::now()))?;
+
+        self.open()
+    }
+}
+
+/// Name of a rotated log file, `screen.log` becomes
+/// `screen.2023-08-25_18-02-47_123456.log` like with loguru.
+fn rotated_path(path: &Path, time: &DateTime<Local>) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let time = time.format("%Y-%m-%d_%H-%M-%S_%6f");
+
+    match path.extension() {
+        Some(ext) => path.with_file_name(format!("{}.{}.{}", stem, time, ext.to_string_lossy())),
+        None => path.with_file_name(format!("{}.{}", stem, time)),
+    }
+}
+
+/// Format a log file line, `2023-08-25 18:02:47.123 | INFO    | message`.
+pub fn format_line(time: &DateTime<Local>, level: Level, message: &str) -> String {
+    format!(
+        "{} | {:7} | {}",
+        time.format("%Y-%m-%d %H:%M:%S%.3f"),
+        level_name(level),
+        message
+    )
+}
+
+/// Level name as loguru spells it.
+fn level_name(level: Level) -> &'static str {
+    match level {
+        Level::Error => "ERROR",
+        Level::Warn => "WARNING",
+        Level::Info => "INFO",
+        Level::Debug => "DEBUG",
+        Level::Trace => "TRACE",
+    }
+}
+
+/// Parse a level name, `debug`, `info`, `warning`, `error` and so on.
+pub fn parse_level(s: &str) -> Result<LevelFilter, String> {
+    match s.trim().to_ascii_lowercase().as_str() {
+        "off" => Ok(LevelFilter::Off),
+        "error" => Ok(LevelFilter::Error),
+        "warning" | "warn" => Ok(LevelFilter::Warn),
+        "info" => Ok(LevelFilter::Info),
+        "debug" => Ok(LevelFilter::Debug),
+        "trace" => Ok(LevelFilter::Trace),
+        _ => Err(format!(
+            "expected off, error, warning, info, debug or trace, got '{}'",
+            s
+        )),
+    }
+}
+
+struct FileSink {
+    file: Option<RotatingFile>,
+    level: LevelFilter,
+}
+
+struct Logger {
+    sink: Mutex<FileSink>,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        // Stdout is left to command output, e.g. `--list-screens --json`
+        if record.level() <= CONSOLE_LEVEL {
+            match record.level() {
+                Level::Error => eprintln!("Error: {}", record.args()),
+                _ => eprintln!("{}", record.args()),
+            }
+        }
+
+        let mut sink = self.sink.lock().unwrap_or_else(|err| err.into_inner());
+
+        if record.level() > sink.level {
+            return;
+        }
+
+        let line = format_line(&Local::now(), record.level(), &record.args().to_string());
+
+        if let Some(file) = &mut sink.file {
+            if let Err(err) = file.write_line(&line) {
+                // Keep going without the file rather than failing on every message
+                eprintln!(
+                    "Error: can't write to {}, logging to the console only: {}",
+                    file.path.display(),
+                    err
+                );
+                sink.file = None;
+            }
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Log to stderr and, if `log_dir` is set, to `screen.log` in it.
+///
+/// Everything is written to the file until [`set_file_level`] is called.
+/// Calling this more than once has no effect.
+pub fn init_logging(log_dir: Option<&Path>) {
+    let mut initialized = false;
+    let logger = LOGGER.get_or_init(|| {
+        initialized = true;
+        Logger {
+            sink: Mutex::new(FileSink {
+                file: log_dir.map(|dir| RotatingFile::new(dir.join("screen.log"), ROTATION_SIZE)),
+                level: LevelFilter::Trace,
+            }),
+        }
+    });
+
+    if initialized && log::set_logger(logger).is_ok() {
+        log::set_max_level(LevelFilter::Trace);
+
+        if log_dir.is_none() {
+            log::warn!("No directory for screen.log, logging to the console only");
+        }
+    }
+}
+
+/// Only write messages of `level` and above to the log file.
+pub fn set_file_level(level: LevelFilter) {
+    if let Some(logger) = LOGGER.get() {
+        let mut sink = logger.sink.lock().unwrap_or_else(|err| err.into_inner());
+        sink.level = level;
+        log::set_max_level(level.max(CONSOLE_LEVEL));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn line_format_matches_the_matcher() {
+        let time = Local.with_ymd_and_hms(2023, 8, 25, 18, 2, 47).unwrap();
+
+        assert_eq!(
+            format_line(&time, Level::Info, "Screenshot saved"),
+            "2023-08-25 18:02:47.000 | INFO    | Screenshot saved"
+        );
+        assert_eq!(
+            format_line(&time, Level::Warn, "No tooltip"),
+            "2023-08-25 18:02:47.000 | WARNING | No tooltip"
+        );
+    }
+
+    #[test]
+    fn parse_levels() {
+        assert_eq!(parse_level("DEBUG"), Ok(LevelFilter::Debug));
+        assert_eq!(parse_level("warning"), Ok(LevelFilter::Warn));
+        assert_eq!(parse_level("warn"), Ok(LevelFilter::Warn));
+        assert!(parse_level("verbose").is_err());
+    }
+
+    #[test]
+    fn rotates_when_full() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("logs").join("screen.log");
+        let mut file = RotatingFile::new(path.clone(), 25);
+
+        file.write_line("first line").unwrap();
+        file.write_line("second line").unwrap();
+        assert_eq!(fs::read_dir(path.parent().unwrap()).unwrap().count(), 1);
+
+        file.write_line("third line").unwrap();
+
+        let mut names: Vec<_> = fs::read_dir(path.parent().unwrap())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+
+        assert_eq!(names.len(), 2);
+        assert!(names[0].starts_with("screen.") && names[0].ends_with(".log"));
+        assert_eq!(names[1], "screen.log");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "third line\n");
+    }
+}
@@ -1,113 +1,377 @@
+mod cli;
+
 use chrono::Local;
-use ini::Ini;
-use screenshots::Screen;
-use std::path::Path;
-use std::path::PathBuf;
-use std::{env, println};
+use clap::Parser;
+use cli::{Cli, Command, ConfigCommand};
+use log::{error, info, warn};
+use screen::{
+    all_screens, daemon, init_logging, init_workspace, layered, poe_config_path, read_metadata,
+    resolve_config, resolve_screen_id, resolve_target, screen_by_id, set_file_level, unique_path,
+    CaptureTarget, Capturer, Config, EntryKind, Environment, InitAction, InitEntry, PoeConfig,
+    ScreenError, ScreenInfo, ScreenSelector, Source, Sources,
+};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
-fn main() {
-    // Prepare paths
-    let cur_dir = env::current_dir().unwrap();
-    let workdir = cur_dir.as_path();
-    let screen_dir = workdir.join("data").join("queue");
-    let cfg_path = workdir.join("config.ini");
-    let config_path_str = cfg_path.as_path().to_str().unwrap();
-
-    // Load config
-    let mut screen_id: i32 = match Ini::load_from_file(config_path_str) {
-        Ok(ini_file) => ini_file
-            .get_from_or(Some("screenshot"), "screen", "0")
-            .parse::<i32>()
-            .unwrap(),
-        Err(_) => 0 as i32,
-    };
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let env = Environment::from_process();
+
+    // Set up before anything else, so errors loading the config are logged too
+    let log_dir = log_dir(&cli, &env);
+    init_logging(log_dir.0.as_deref());
+
+    match run(cli, env, log_dir) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            error!("{}", err);
+            ExitCode::from(err.exit_code())
+        }
+    }
+}
+
+/// Effective settings, with where each of them came from.
+struct Settings {
+    config: Config,
+    sources: Sources,
+    config_path: (PathBuf, Source),
+    profile: (Option<String>, Source),
+    output_dir: (PathBuf, Source),
+    log_dir: (Option<PathBuf>, Source),
+    poe_config: (Option<PathBuf>, Source),
+}
+
+/// Where `screen.log` is written, `data/logs` next to the config or `logs` in
+/// the output directory unless it's given.
+fn log_dir(cli: &Cli, env: &Environment) -> (Option<PathBuf>, Source) {
+    layered(
+        cli.log_dir.clone().map(Some),
+        env.path("LOG_DIR").map(Some),
+        || {
+            let config = cli.config.clone().or_else(|| env.path("CONFIG"));
+            let output_dir = cli.output_dir.clone().or_else(|| env.path("OUTPUT_DIR"));
 
-    if screen_id == -1 {
-        // Auto-detect
-        let poe_config_path = poe_config_path().unwrap();
-        screen_id = match active_monitor_number_from_poe_config(poe_config_path) {
-            Some(val) => val as i32,
-            None => {
-                println!("Couldn't auto-detect PoE screen, defaulting to 0");
-                0
+            match (config, output_dir) {
+                (Some(config), _) => Some(config.parent()?.join("data").join("logs")),
+                (None, Some(output_dir)) => Some(output_dir.join("logs")),
+                (None, None) => Some(env::current_dir().ok()?.join("data").join("logs")),
             }
+        },
+    )
+}
+
+fn run(cli: Cli, env: Environment, log_dir: (Option<PathBuf>, Source)) -> Result<(), ScreenError> {
+    let poe_config = layered(
+        cli.poe_config.clone().map(Some),
+        env.path("POE_CONFIG").map(Some),
+        poe_config_path,
+    );
+
+    if cli.list_screens {
+        return list_screens(poe_config.0.as_deref(), cli.json);
+    }
+
+    if let Some(Command::Init { dir, force }) = &cli.command {
+        return init(dir.as_deref(), poe_config.0.as_deref(), *force);
+    }
+
+    if let Some(Command::Meta { file }) = &cli.command {
+        for (keyword, text) in read_metadata(file)? {
+            println!("{}: {}", keyword, text);
         }
+
+        return Ok(());
     }
 
-    println!("Using screen ID: {}", screen_id);
+    if let Some(Command::Trigger { socket }) = &cli.command {
+        for image_path in daemon::trigger(socket)? {
+            info!("Screenshot saved to: {}", image_path.display());
+        }
 
-    // Prepare image path
-    let local_time = Local::now();
-    let filename = String::from(local_time.format("%Y-%m-%d-%H-%M-%S").to_string())
-        + String::from(".png").as_str();
-    let image_path_buf = screen_dir.join(filename);
-    let image_path = image_path_buf.as_path();
+        return Ok(());
+    }
 
-    // Get all screens
-    let screens = Screen::all().unwrap();
+    let settings = load_settings(&cli, &env, log_dir, poe_config)?;
 
-    // Use the first one
-    if screen_id as usize >= screens.len() {
-        println!(
-            "Error: Cannot use screen {}, you only have {} screen(s) (IDs go from 0 to {})",
-            screen_id,
-            screens.len(),
-            screens.len() - 1,
+    if let Some(Command::Config {
+        command: ConfigCommand::Check,
+    }) = &cli.command
+    {
+        print_config(&settings);
+        return Ok(());
+    }
+
+    let Settings {
+        config,
+        output_dir: (screen_dir, _),
+        poe_config: (poe_config, _),
+        ..
+    } = settings;
+
+    set_file_level(config.log_level);
+    let target = resolve_target(&config.screen, poe_config.as_deref())?;
+
+    match target {
+        CaptureTarget::Screen(screen_id, _) => info!("Using screen ID: {}", screen_id),
+        target => info!("Capturing {}", target),
+    }
+
+    let poe = poe_config.as_deref().and_then(load_poe_config);
+
+    if cli.dry_run {
+        warn_poe_mismatches(poe.as_ref(), None, &config);
+        return dry_run(target, &config, &screen_dir);
+    }
+
+    let capturer = Capturer::new(target, screen_dir, config.clone())?;
+    warn_poe_mismatches(poe.as_ref(), capturer.screen_info(), &config);
+
+    if let Some(Command::Daemon { socket }) = &cli.command {
+        return daemon::serve(&capturer, socket);
+    }
+
+    for capture in capturer.capture()? {
+        if let Some((x, y)) = capture.cursor {
+            info!("Mouse pointer at ({}, {})", x, y);
+        }
+
+        if capture.scale != 1.0 {
+            info!(
+                "Normalized from {}x{}px, scaled by {}",
+                capture.original_size.0, capture.original_size.1, capture.scale
+            );
+        }
+
+        info!(
+            "Screenshot saved to: {} (encoded in {} ms)",
+            capture.path.display(),
+            capture.encode_time.as_millis()
         );
-    } else {
-        let screen = screens[screen_id as usize];
+    }
+
+    Ok(())
+}
+
+/// Set up `dir` for the capturer and the matcher, see [`init_workspace`].
+fn init(dir: Option<&Path>, poe_config: Option<&Path>, force: bool) -> Result<(), ScreenError> {
+    let workdir = env::current_dir().map_err(ScreenError::WorkingDir)?;
+    let root = match dir {
+        Some(dir) => workdir.join(dir),
+        None => workdir,
+    };
+
+    let screen = poe_config
+        .and_then(load_poe_config)
+        .and_then(|poe| poe.monitor())
+        .unwrap_or_else(|| {
+            warn!("Couldn't auto-detect PoE screen, defaulting to 0");
+            0
+        });
+    let exe = env::current_exe().unwrap_or_else(|_| root.join("screen"));
+
+    for InitEntry { path, kind, action } in init_workspace(&root, screen, &exe, force)? {
+        match (kind, action) {
+            (EntryKind::File, InitAction::Kept) => {
+                info!("Kept {} (use --force to overwrite)", path.display())
+            }
+            (_, action) => info!("{} {}", action, path.display()),
+        }
+    }
 
-        // Make screenshot
-        let image = screen.capture().unwrap();
-        image.save(image_path).unwrap();
+    Ok(())
+}
 
-        println!("Screenshot saved to: {}", &image_path.to_str().unwrap());
+/// Warn about PoE settings that hurt matching, `screen` is the one captured
+/// if it's a single fixed one.
+fn warn_poe_mismatches(poe: Option<&PoeConfig>, screen: Option<&ScreenInfo>, config: &Config) {
+    for mismatch in poe
+        .map(|poe| poe.mismatches(screen, config))
+        .unwrap_or_default()
+    {
+        warn!("{}", mismatch);
     }
 }
 
-/// cross-platform C:\Users\username\Documents\My Games\Path of Exile\production_Config.ini
-fn poe_config_path() -> Option<PathBuf> {
-    dirs_next::document_dir().map(|docs_dir| {
-        docs_dir
-            .join("My Games")
-            .join("Path of Exile")
-            .join("production_Config.ini")
+/// Load PoE's config, only warning if it can't be read.
+///
+/// Commands only use it for defaults and checks, so a broken or missing
+/// config never stops them.
+fn load_poe_config(path: &Path) -> Option<PoeConfig> {
+    PoeConfig::load(path).unwrap_or_else(|err| {
+        warn!("Ignoring PoE's config: {}", err);
+        None
     })
 }
 
-/// Read poe production_Config.ini, try to find the index of the preferred minitor.
-/// Something like this:
-///
-///  adapter_name=AMD Radeon RX 5700 XT(#0)
-///
-fn active_monitor_number_from_poe_config<P>(poe_config_path: P) -> Option<usize>
-where
-    P: AsRef<Path>,
-{
-    let poe_config_path = poe_config_path.as_ref();
+/// Print what would be captured and where it would be saved.
+fn dry_run(target: CaptureTarget, config: &Config, screen_dir: &Path) -> Result<(), ScreenError> {
+    let screen_ids = match target {
+        CaptureTarget::Screen(screen_id, _) => vec![screen_id],
+        CaptureTarget::Cursor => vec![resolve_screen_id(&ScreenSelector::Cursor, None)?],
+        CaptureTarget::All => (0..all_screens()?.len()).collect(),
+    };
+    let stitch = target == CaptureTarget::All && config.stitch;
+
+    for screen_id in screen_ids {
+        let info = screen_by_id(screen_id)?.display_info;
+        let (width, height) = match (config.region, config.cursor_window) {
+            (Some(region), _) => (region.width, region.height),
+            (None, Some(size)) => (size.width.min(info.width), size.height.min(info.height)),
+            (None, None) => (info.width, info.height),
+        };
+
+        print!(
+            "Dry run: would capture {}x{}px of screen {}",
+            width, height, screen_id
+        );
+
+        if !stitch {
+            let path = unique_path(
+                screen_dir,
+                &config.filename,
+                &Local::now(),
+                &screen_id.to_string(),
+                config.format,
+            );
+            print!(" and save to: {}", path.display());
+        }
 
-    if !poe_config_path.exists() {
-        println!("PoE config path doesn't exist (are you on Linux?)");
-        return None;
+        println!();
     }
 
-    let ini_file = Ini::load_from_file(poe_config_path.to_str().unwrap()).unwrap();
+    if stitch {
+        println!(
+            "Dry run: would stitch the screens and save to: {}",
+            unique_path(
+                screen_dir,
+                &config.filename,
+                &Local::now(),
+                "all",
+                config.format
+            )
+            .display()
+        );
+    }
 
-    let adapter_name = ini_file.get_from_or(Some("DISPLAY"), "adapter_name", "(#0)");
+    Ok(())
+}
 
-    let Some(start) = adapter_name.rfind("(#") else {
-        return None;
-    };
+/// Load `config.ini` and apply the environment and the flags on top of it.
+fn load_settings(
+    cli: &Cli,
+    env: &Environment,
+    log_dir: (Option<PathBuf>, Source),
+    poe_config: (Option<PathBuf>, Source),
+) -> Result<Settings, ScreenError> {
+    // Prepare paths
+    let workdir = env::current_dir().map_err(ScreenError::WorkingDir)?;
+    let output_dir = layered(cli.output_dir.clone(), env.path("OUTPUT_DIR"), || {
+        workdir.join("data").join("queue")
+    });
+    let config_path = layered(cli.config.clone(), env.path("CONFIG"), || {
+        workdir.join("config.ini")
+    });
 
-    let Some(end) = adapter_name.rfind(")") else {
-        return None;
-    };
+    let profile = layered(
+        cli.profile.clone().map(Some),
+        env.get("PROFILE")
+            .filter(|profile| !profile.is_empty())
+            .map(|profile| Some(profile.to_string())),
+        || None,
+    );
+
+    let (config, sources) = resolve_config(
+        &config_path.0,
+        profile.0.as_deref(),
+        env,
+        &cli.overrides(),
+        &workdir,
+    )?;
+
+    Ok(Settings {
+        config,
+        sources,
+        config_path,
+        profile,
+        output_dir,
+        log_dir,
+        poe_config,
+    })
+}
 
-    let Some(substr) = adapter_name.get(start + 2..end) else {
-        return None;
+/// Print the effective settings and where each of them came from.
+fn print_config(settings: &Settings) {
+    let (config_path, config_source) = &settings.config_path;
+    let (output_dir, output_source) = &settings.output_dir;
+    let (log_dir, log_source) = &settings.log_dir;
+    let (poe_config, poe_source) = &settings.poe_config;
+    let display = |path: &Option<PathBuf>| {
+        path.as_ref()
+            .map_or("none".to_string(), |path| path.display().to_string())
     };
 
-    let monitor_index = substr.parse::<usize>().ok()?;
+    println!("; config: {} ({})", config_path.display(), config_source);
+    if let (Some(profile), source) = &settings.profile {
+        println!("; profile: {} ({})", profile, source);
+    }
+
+    println!("; output_dir: {} ({})", output_dir.display(), output_source);
+    println!("; log_dir: {} ({})", display(log_dir), log_source);
+    println!("; poe_config: {} ({})", display(poe_config), poe_source);
+
+    if let Some(poe) = settings.poe_config.0.as_deref().and_then(load_poe_config) {
+        let unknown = || "unknown".to_string();
+
+        println!(
+            "; PoE: adapter {}, resolution {}, {}",
+            poe.adapter_name.clone().unwrap_or_else(unknown),
+            poe.resolution.map_or_else(unknown, |size| size.to_string()),
+            poe.window_mode
+                .map_or_else(unknown, |mode| mode.to_string()),
+        );
+
+        for mismatch in poe.mismatches(None, &settings.config) {
+            warn!("{}", mismatch);
+        }
+    }
+
+    let lines: Vec<_> = settings
+        .config
+        .entries()
+        .into_iter()
+        .map(|(key, value)| (format!("{} = {}", key, value), settings.sources.get(key)))
+        .collect();
+    let width = lines.iter().map(|(line, _)| line.len()).max().unwrap_or(0);
+
+    println!("[screenshot]");
+
+    for (line, source) in lines {
+        println!("{:width$}  ; {}", line, source, width = width);
+    }
+}
+
+fn list_screens(poe_config: Option<&Path>, json: bool) -> Result<(), ScreenError> {
+    let poe_screen = poe_config
+        .and_then(load_poe_config)
+        .and_then(|poe| poe.monitor());
+    let screens = screen::list_screens(poe_screen)?;
+
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&screens).expect("screen list is serializable")
+        );
+        return Ok(());
+    }
+
+    for info in &screens {
+        println!("{}", info);
+    }
+
+    if poe_screen.is_none() {
+        println!("PoE screen couldn't be detected, screen = -1 will use screen 0");
+    }
 
-    Some(monitor_index)
+    Ok(())
 }
@@ -0,0 +1,346 @@
+//! Template matching with normalized squared differences, the equivalent of
+//! OpenCV's `matchTemplate` with `TM_SQDIFF_NORMED` followed by `minMaxLoc`.
+//!
+//! Scores are computed like OpenCV does, so the thresholds tuned for the
+//! Python matcher (`THRESHOLD_CONTROL = 0.16`) apply as they are:
+//!
+//! ```text
+//! R(x, y) = sum((T(x', y') - I(x + x', y + y'))^2) / sqrt(sum(T(x', y')^2) * sum(I(x + x', y + y')^2))
+//! ```
+//!
+//! Windows where the normalization isn't defined (e.g. all black) score 1.
+
+use image::{GrayImage, ImageBuffer, Luma, RgbaImage};
+use rustfft::num_complex::Complex;
+use rustfft::{FftDirection, FftPlanner};
+
+/// Map of scores for every position of the template, like `cv2.matchTemplate` returns.
+pub type ScoreMap = ImageBuffer<Luma<f32>, Vec<f32>>;
+
+/// Best position of a template in an image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Match {
+    /// Normalized squared difference, 0 is a perfect match.
+    pub min_val: f32,
+    /// Top left corner of the template in the image, `(x, y)`.
+    pub min_loc: (u32, u32),
+}
+
+/// Find the best match of `template` in `image`, like `cv2.matchTemplate`
+/// with `TM_SQDIFF_NORMED` followed by `cv2.minMaxLoc`.
+///
+/// Return `None` if the template is larger than the image.
+pub fn match_template(image: &GrayImage, template: &GrayImage) -> Option<Match> {
+    PreparedImage::new(image).find(template)
+}
+
+/// Convert to grayscale the same way as OpenCV's `COLOR_RGB2GRAY`, alpha is ignored.
+pub fn to_gray(image: &RgbaImage) -> GrayImage {
+    weighted_gray(image, [4899, 9617, 1868])
+}
+
+/// Convert a screenshot to grayscale the way the matcher loads it.
+///
+/// `cv2.imread` returns BGR, which the matcher converts with `COLOR_RGB2GRAY`,
+/// so red gets the weight of blue and the other way around. The templates
+/// are loaded as RGB and go through [`to_gray`].
+pub fn screenshot_to_gray(image: &RgbaImage) -> GrayImage {
+    weighted_gray(image, [1868, 9617, 4899])
+}
+
+/// OpenCV's fixed-point conversion, weights of red, green and blue out of `1 << 14`.
+fn weighted_gray(image: &RgbaImage, [wr, wg, wb]: [u32; 3]) -> GrayImage {
+    GrayImage::from_fn(image.width(), image.height(), |x, y| {
+        let [r, g, b, _] = image.get_pixel(x, y).0;
+        let gray = (r as u32 * wr + g as u32 * wg + b as u32 * wb + (1 << 13)) >> 14;
+
+        image::Luma([gray as u8])
+    })
+}
+
+/// Image prepared for searching several templates in it.
+///
+/// The cross-correlation is computed in the frequency domain, so the
+/// image spectrum and its integral of squares are only computed once.
+pub struct PreparedImage {
+    width: usize,
+    height: usize,
+    spectrum: Vec<Complex<f64>>,
+    /// Summed-area table of squared pixel values, `(width + 1) * (height + 1)`.
+    sq_integral: Vec<f64>,
+}
+
+impl PreparedImage {
+    pub fn new(image: &GrayImage) -> Self {
+        let width = image.width() as usize;
+        let height = image.height() as usize;
+
+        let mut spectrum: Vec<Complex<f64>> = image
+            .pixels()
+            .map(|pixel| Complex::new(pixel.0[0] as f64, 0.0))
+            .collect();
+        fft2d(&mut spectrum, width, height, FftDirection::Forward);
+
+        let mut sq_integral = vec![0.0; (width + 1) * (height + 1)];
+
+        for y in 0..height {
+            let mut row_sum = 0.0;
+
+            for x in 0..width {
+                let val = image.get_pixel(x as u32, y as u32).0[0] as f64;
+                row_sum += val * val;
+                sq_integral[(y + 1) * (width + 1) + x + 1] =
+                    sq_integral[y * (width + 1) + x + 1] + row_sum;
+            }
+        }
+
+        PreparedImage {
+            width,
+            height,
+            spectrum,
+            sq_integral,
+        }
+    }
+
+    /// Sum of squared pixels in the window at `(x, y)` of size `w`x`h`.
+    fn window_sq_sum(&self, x: usize, y: usize, w: usize, h: usize) -> f64 {
+        let stride = self.width + 1;
+        let at = |x: usize, y: usize| self.sq_integral[y * stride + x];
+
+        at(x + w, y + h) - at(x, y + h) - at(x + w, y) + at(x, y)
+    }
+
+    /// Find the best match of `template`.
+    ///
+    /// Ties are resolved like `minMaxLoc`, the first position in row-major order wins.
+    /// Return `None` if the template is larger than the image.
+    pub fn find(&self, template: &GrayImage) -> Option<Match> {
+        let scores = self.score_map(template)?;
+        let mut best = Match {
+            min_val: f32::INFINITY,
+            min_loc: (0, 0),
+        };
+
+        for (x, y, score) in scores.enumerate_pixels() {
+            if score.0[0] < best.min_val {
+                best = Match {
+                    min_val: score.0[0],
+                    min_loc: (x, y),
+                };
+            }
+        }
+
+        Some(best)
+    }
+
+    /// Score every position of `template`, the map has
+    /// `(width - template width + 1)`x`(height - template height + 1)` pixels.
+    ///
+    /// Return `None` if the template is larger than the image.
+    pub fn score_map(&self, template: &GrayImage) -> Option<ScoreMap> {
+        let w = template.width() as usize;
+        let h = template.height() as usize;
+
+        if w == 0 || h == 0 || w > self.width || h > self.height {
+            return None;
+        }
+
+        // Cross-correlation through the spectra, the template is zero-padded
+        // to the image size. Results for positions where the template fits
+        // never wrap around, so they're the same as a direct computation.
+        let mut padded = vec![Complex::new(0.0, 0.0); self.width * self.height];
+        let mut templ_sq_sum = 0.0;
+
+        for (x, y, pixel) in template.enumerate_pixels() {
+            let val = pixel.0[0] as f64;
+            padded[y as usize * self.width + x as usize] = Complex::new(val, 0.0);
+            templ_sq_sum += val * val;
+        }
+
+        fft2d(&mut padded, self.width, self.height, FftDirection::Forward);
+
+        for (t, i) in padded.iter_mut().zip(&self.spectrum) {
+            *t = i * t.conj();
+        }
+
+        fft2d(&mut padded, self.width, self.height, FftDirection::Inverse);
+
+        let scale = 1.0 / (self.width * self.height) as f64;
+        let templ_norm = templ_sq_sum.sqrt();
+        let map_width = self.width - w + 1;
+        let map_height = self.height - h + 1;
+
+        Some(ScoreMap::from_fn(
+            map_width as u32,
+            map_height as u32,
+            |x, y| {
+                let (x, y) = (x as usize, y as usize);
+                let ccorr = padded[y * self.width + x].re * scale;
+                let wnd_sq_sum = self.window_sq_sum(x, y, w, h);
+
+                Luma([normalize(
+                    wnd_sq_sum - 2.0 * ccorr + templ_sq_sum,
+                    wnd_sq_sum,
+                    templ_norm,
+                )])
+            },
+        ))
+    }
+}
+
+/// Normalize a squared difference the way OpenCV's `TM_SQDIFF_NORMED` does.
+fn normalize(sqdiff: f64, wnd_sq_sum: f64, templ_norm: f64) -> f32 {
+    let t = wnd_sq_sum.max(0.0).sqrt() * templ_norm;
+
+    if sqdiff.abs() < t {
+        (sqdiff / t) as f32
+    } else if sqdiff.abs() < t * 1.125 {
+        sqdiff.signum() as f32
+    } else {
+        1.0
+    }
+}
+
+/// In-place 2D FFT of a row-major `width`x`height` buffer.
+fn fft2d(data: &mut [Complex<f64>], width: usize, height: usize, direction: FftDirection) {
+    let mut planner = FftPlanner::new();
+
+    planner.plan_fft(width, direction).process(data);
+
+    let mut columns = vec![Complex::new(0.0, 0.0); data.len()];
+    transpose(data, &mut columns, width, height);
+    planner.plan_fft(height, direction).process(&mut columns);
+    transpose(&columns, data, height, width);
+}
+
+fn transpose(src: &[Complex<f64>], dst: &mut [Complex<f64>], width: usize, height: usize) {
+    for y in 0..height {
+        for x in 0..width {
+            dst[x * height + y] = src[y * width + x];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::{Path, PathBuf};
+
+    fn repo_path(path: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("..").join(path)
+    }
+
+    fn load_gray(path: &str) -> GrayImage {
+        to_gray(&image::open(repo_path(path)).unwrap().to_rgba8())
+    }
+
+    /// Direct computation of the score at `(x, y)`, for comparison.
+    fn naive_score(image: &GrayImage, template: &GrayImage, x: u32, y: u32) -> f32 {
+        let (mut sqdiff, mut templ_sq_sum, mut wnd_sq_sum) = (0.0, 0.0, 0.0);
+
+        for (tx, ty, pixel) in template.enumerate_pixels() {
+            let t = pixel.0[0] as f64;
+            let i = image.get_pixel(x + tx, y + ty).0[0] as f64;
+            sqdiff += (t - i) * (t - i);
+            templ_sq_sum += t * t;
+            wnd_sq_sum += i * i;
+        }
+
+        normalize(sqdiff, wnd_sq_sum, templ_sq_sum.sqrt())
+    }
+
+    /// Deterministic noise so the tests don't need a RNG.
+    fn noise(width: u32, height: u32) -> GrayImage {
+        GrayImage::from_fn(width, height, |x, y| {
+            Luma([((x * 7919 + y * 104_729 + x * y * 31) % 251) as u8])
+        })
+    }
+
+    #[test]
+    fn gray_conversion_matches_opencv() {
+        let image = RgbaImage::from_pixel(1, 1, image::Rgba([200, 100, 50, 0]));
+
+        // cv2.cvtColor(np.array([[[200, 100, 50]]], np.uint8), cv2.COLOR_RGB2GRAY)
+        assert_eq!(to_gray(&image).get_pixel(0, 0).0[0], 124);
+        // Same with the channels in imread's BGR order, [[[50, 100, 200]]]
+        assert_eq!(screenshot_to_gray(&image).get_pixel(0, 0).0[0], 96);
+    }
+
+    #[test]
+    fn score_map_matches_direct_computation() {
+        let image = noise(40, 30);
+        let template = image::imageops::crop_imm(&image, 5, 3, 8, 6).to_image();
+        let scores = PreparedImage::new(&image).score_map(&template).unwrap();
+
+        assert_eq!(scores.dimensions(), (33, 25));
+
+        for (x, y, score) in scores.enumerate_pixels() {
+            let expected = naive_score(&image, &template, x, y);
+            assert!((score.0[0] - expected).abs() < 1e-5, "({}, {})", x, y);
+        }
+    }
+
+    #[test]
+    fn template_larger_than_image() {
+        assert_eq!(match_template(&noise(10, 10), &noise(11, 5)), None);
+    }
+
+    #[test]
+    fn black_window_scores_one() {
+        let image = GrayImage::new(20, 20);
+        let found = match_template(&image, &noise(5, 5)).unwrap();
+
+        assert_eq!(found.min_val, 1.0);
+        assert_eq!(found.min_loc, (0, 0));
+    }
+
+    #[test]
+    fn finds_templates_pasted_into_noise() {
+        for name in [
+            "unique-one-line-fullhd.png",
+            "unique-one-line-end-fullhd.png",
+            "unique-two-line-fullhd.png",
+            "unique-two-line-end-fullhd.png",
+        ] {
+            let template = load_gray(&format!("templates/{}", name));
+            let mut image = noise(200, 120);
+            image::imageops::replace(&mut image, &template, 123, 45);
+
+            let found = match_template(&image, &template).unwrap();
+
+            assert_eq!(found.min_loc, (123, 45), "{}", name);
+            assert!(found.min_val < 1e-6, "{}: {}", name, found.min_val);
+        }
+    }
+
+    #[test]
+    fn finds_unidentified_title_in_screenshot() {
+        let screenshot = screenshot_to_gray(
+            &image::open(repo_path(
+                "tests/test_data/contains/example/Bones_of_Ullr/Screenshot from 2023-08-25 18-02-47.png",
+            ))
+            .unwrap()
+            .to_rgba8(),
+        );
+        // The tooltip is in the bottom right, keep the test fast in debug builds
+        let area = image::imageops::crop_imm(&screenshot, 1400, 500, 520, 400).to_image();
+        let prepared = PreparedImage::new(&area);
+
+        let one_line = prepared
+            .find(&load_gray("templates/unique-one-line-fullhd.png"))
+            .unwrap();
+        let two_line = prepared
+            .find(&load_gray("templates/unique-two-line-fullhd.png"))
+            .unwrap();
+
+        // The matcher's score, cv2.matchTemplate on the screenshot loaded by
+        // Matcher.load_screen and the template converted by utils.image_to_cv
+        assert_eq!(one_line.min_loc, (1628 - 1400, 670 - 500));
+        assert!(
+            (one_line.min_val - 0.071_082).abs() < 1e-4,
+            "{}",
+            one_line.min_val
+        );
+        assert!(two_line.min_val > 0.16);
+    }
+}
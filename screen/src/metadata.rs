@@ -0,0 +1,173 @@
+//! Provenance of a screenshot embedded in PNG text chunks, so it travels with
+//! the image when the sidecar gets lost.
+
+use crate::capture::ScreenChoice;
+use crate::error::ScreenError;
+use crate::screens::ScreenInfo;
+use crate::sidecar::VERSION;
+use chrono::{DateTime, Local, SecondsFormat};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+/// What is known about a screenshot before it's encoded.
+#[derive(Debug, Clone, Copy)]
+pub struct Provenance<'a> {
+    pub time: &'a DateTime<Local>,
+    pub screen: Option<&'a ScreenInfo>,
+    pub chosen_by: ScreenChoice,
+    /// Size of the captured image before normalizing and cropping.
+    pub resolution: (u32, u32),
+    pub session: Option<&'a str>,
+}
+
+impl Provenance<'_> {
+    /// Keyword and text of each chunk.
+    pub fn text_chunks(&self) -> Vec<(String, String)> {
+        let mut chunks = vec![
+            (
+                "Creation Time".to_string(),
+                self.time.to_rfc3339_opts(SecondsFormat::Millis, false),
+            ),
+            (
+                "Software".to_string(),
+                format!("unique-matcher screen {}", VERSION),
+            ),
+            (
+                "Screen".to_string(),
+                self.screen
+                    .map_or("all".to_string(), |info| info.index.to_string()),
+            ),
+        ];
+
+        if let Some(info) = self.screen {
+            let display = match &info.name {
+                Some(name) => format!("{} ({})", info.id, name),
+                None => info.id.to_string(),
+            };
+
+            chunks.push(("Display".to_string(), display));
+        }
+
+        chunks.push((
+            "Resolution".to_string(),
+            format!("{}x{}", self.resolution.0, self.resolution.1),
+        ));
+        chunks.push(("Detection".to_string(), self.chosen_by.to_string()));
+
+        if let Some(session) = self.session {
+            chunks.push(("Session".to_string(), session.to_string()));
+        }
+
+        chunks
+    }
+}
+
+/// Read the text chunks of a PNG file, in the order they were written.
+pub fn read_metadata(path: &Path) -> Result<Vec<(String, String)>, ScreenError> {
+    let metadata_error = |reason: String| ScreenError::Metadata {
+        path: path.to_path_buf(),
+        reason,
+    };
+
+    let file = File::open(path).map_err(|err| metadata_error(err.to_string()))?;
+    let reader = png::Decoder::new(BufReader::new(file))
+        .read_info()
+        .map_err(|err| metadata_error(err.to_string()))?;
+    let info = reader.info();
+
+    let mut chunks = info
+        .uncompressed_latin1_text
+        .iter()
+        .map(|chunk| (chunk.keyword.clone(), chunk.text.clone()))
+        .collect::<Vec<_>>();
+
+    for chunk in &info.compressed_latin1_text {
+        let text = chunk
+            .get_text()
+            .map_err(|err| metadata_error(err.to_string()))?;
+        chunks.push((chunk.keyword.clone(), text));
+    }
+
+    for chunk in &info.utf8_text {
+        let text = chunk
+            .get_text()
+            .map_err(|err| metadata_error(err.to_string()))?;
+        chunks.push((chunk.keyword.clone(), text));
+    }
+
+    Ok(chunks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::{save_atomically, OutputFormat, PngCompression};
+    use chrono::TimeZone;
+    use image::RgbaImage;
+
+    #[test]
+    fn metadata_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("shot.png");
+        let time = Local.with_ymd_and_hms(2023, 8, 25, 18, 2, 47).unwrap();
+        let screen = ScreenInfo {
+            index: 1,
+            id: 65537,
+            name: Some("Ünïcode".to_string()),
+            x: 0,
+            y: 0,
+            width: 2560,
+            height: 1440,
+            scale_factor: 1.0,
+            is_primary: false,
+            poe_detected: true,
+        };
+        let provenance = Provenance {
+            time: &time,
+            screen: Some(&screen),
+            chosen_by: ScreenChoice::PoeConfig,
+            resolution: (2560, 1440),
+            session: Some("settlers"),
+        };
+        let chunks = provenance.text_chunks();
+
+        save_atomically(
+            &RgbaImage::new(4, 4),
+            &path,
+            OutputFormat::Png,
+            PngCompression::default(),
+            &chunks,
+        )
+        .unwrap();
+
+        let mut read = read_metadata(&path).unwrap();
+        read.sort();
+        let mut expected = chunks.clone();
+        expected.sort();
+
+        assert_eq!(read, expected);
+        assert!(chunks.contains(&("Detection".to_string(), "poe-config".to_string())));
+        assert!(chunks.contains(&("Display".to_string(), "65537 (Ünïcode)".to_string())));
+    }
+
+    #[test]
+    fn non_png_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("shot.bmp");
+
+        save_atomically(
+            &RgbaImage::new(4, 4),
+            &path,
+            OutputFormat::Bmp,
+            PngCompression::default(),
+            &[],
+        )
+        .unwrap();
+
+        assert!(matches!(
+            read_metadata(&path),
+            Err(ScreenError::Metadata { .. })
+        ));
+    }
+}
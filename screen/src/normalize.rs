@@ -0,0 +1,116 @@
+//! Rescaling captures to the 1920x1080 geometry the matcher templates are made for.
+//!
+//! PoE scales its UI with the vertical resolution, so a 1440p or 2160p frame
+//! is scaled by `1080 / height`. On ultrawide screens the extra width only
+//! adds game world on the sides, which is cropped to the centered 1920px.
+
+use image::imageops::{self, FilterType};
+use image::RgbaImage;
+
+/// Height the matcher templates are made for.
+pub const FULL_HD_HEIGHT: u32 = 1080;
+
+/// Width of a 16:9 1080p screen.
+pub const FULL_HD_WIDTH: u32 = 1920;
+
+/// Screenshot rescaled to the 1080p coordinate space.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Normalized {
+    pub image: RgbaImage,
+    /// Size of the image before rescaling.
+    pub original_size: (u32, u32),
+    /// Factor the image was scaled by.
+    pub scale: f32,
+}
+
+/// Rescale `image` to the 1080p equivalent of a `screen_height` tall screen.
+///
+/// `part_height` is how much of the screen height the image covers, in the
+/// same units as `screen_height`. Only full screen images (`part_height ==
+/// screen_height`) have their ultrawide sides cropped.
+pub fn normalize(image: RgbaImage, screen_height: u32, part_height: u32) -> Normalized {
+    let original_size = image.dimensions();
+    let (width, height) = original_size;
+
+    // Pixels of the image per unit of screen height, handles HiDPI captures
+    let target_height = (f64::from(part_height) * f64::from(FULL_HD_HEIGHT)
+        / f64::from(screen_height))
+    .round()
+    .max(1.0) as u32;
+    let target_width = (f64::from(width) * f64::from(target_height) / f64::from(height))
+        .round()
+        .max(1.0) as u32;
+
+    let mut image = if (target_width, target_height) == original_size {
+        image
+    } else {
+        imageops::resize(&image, target_width, target_height, FilterType::Triangle)
+    };
+
+    if part_height == screen_height && image.width() > FULL_HD_WIDTH {
+        let left = (image.width() - FULL_HD_WIDTH) / 2;
+        image = imageops::crop_imm(&image, left, 0, FULL_HD_WIDTH, image.height()).to_image();
+    }
+
+    Normalized {
+        image,
+        original_size,
+        scale: target_height as f32 / height as f32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn full_hd_is_unchanged() {
+        let image = RgbaImage::from_pixel(1920, 1080, Rgba([1, 2, 3, 255]));
+        let normalized = normalize(image.clone(), 1080, 1080);
+
+        assert_eq!(normalized.image, image);
+        assert_eq!(normalized.scale, 1.0);
+    }
+
+    #[test]
+    fn qhd_is_scaled_down() {
+        // A quarter of a 1440p screen, full frames are slow to resize in debug builds
+        let normalized = normalize(RgbaImage::new(1280, 720), 1440, 720);
+
+        assert_eq!(normalized.image.dimensions(), (960, 540));
+        assert_eq!(normalized.original_size, (1280, 720));
+        assert_eq!(normalized.scale, 0.75);
+    }
+
+    #[test]
+    fn ultrawide_sides_are_cropped() {
+        let mut image = RgbaImage::new(2560, 1080);
+        image.put_pixel(1280, 540, Rgba([255, 255, 255, 255]));
+
+        let normalized = normalize(image, 1080, 1080);
+
+        assert_eq!(normalized.image.dimensions(), (1920, 1080));
+        assert_eq!(
+            *normalized.image.get_pixel(960, 540),
+            Rgba([255, 255, 255, 255])
+        );
+    }
+
+    #[test]
+    fn part_of_the_screen_is_scaled_but_not_cropped() {
+        let normalized = normalize(RgbaImage::new(2400, 400), 2160, 400);
+
+        assert_eq!(normalized.image.dimensions(), (1200, 200));
+        assert_eq!(normalized.scale, 0.5);
+    }
+
+    #[test]
+    fn hidpi_capture_uses_the_screen_height() {
+        // 2x scaled 1080p screen, 100 desktop units are captured as 200px
+        let normalized = normalize(RgbaImage::new(400, 200), 1080, 100);
+
+        assert_eq!(normalized.image.dimensions(), (200, 100));
+        assert_eq!(normalized.scale, 0.5);
+    }
+}
@@ -0,0 +1,650 @@
+use crate::error::ScreenError;
+use chrono::{DateTime, Local};
+use image::codecs::bmp::BmpEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::error::EncodingError;
+use image::{ColorType, ImageEncoder, ImageError, ImageFormat, ImageResult, RgbaImage};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// Image format screenshots are saved in.
+///
+/// Only lossless formats are offered, the matcher's thresholds are tuned on
+/// artefact-free images, and only ones `cv2.imread` in the matcher can read.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Png,
+    WebpLossless,
+    Bmp,
+}
+
+impl OutputFormat {
+    /// Every format, `tests/test_data/formats` has a screenshot in each of them.
+    pub const ALL: [OutputFormat; 3] = [
+        OutputFormat::Png,
+        OutputFormat::WebpLossless,
+        OutputFormat::Bmp,
+    ];
+
+    /// File extension without the leading dot.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Png => "png",
+            OutputFormat::WebpLossless => "webp",
+            OutputFormat::Bmp => "bmp",
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "png" => Ok(OutputFormat::Png),
+            "webp-lossless" | "webp" => Ok(OutputFormat::WebpLossless),
+            "bmp" => Ok(OutputFormat::Bmp),
+            _ => Err(format!(
+                "unknown format '{}', expected png, webp-lossless or bmp",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputFormat::WebpLossless => f.write_str("webp-lossless"),
+            format => f.write_str(format.extension()),
+        }
+    }
+}
+
+/// Compression level of PNG screenshots, trading encode time for size.
+///
+/// `Default` is the level the screenshots were always saved with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PngCompression {
+    Fast,
+    #[default]
+    Default,
+    Best,
+}
+
+impl PngCompression {
+    fn compression(&self) -> png::Compression {
+        match self {
+            PngCompression::Fast => png::Compression::Fast,
+            PngCompression::Default => png::Compression::Default,
+            PngCompression::Best => png::Compression::Best,
+        }
+    }
+}
+
+impl FromStr for PngCompression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "fast" => Ok(PngCompression::Fast),
+            "default" => Ok(PngCompression::Default),
+            "best" => Ok(PngCompression::Best),
+            _ => Err(format!(
+                "unknown PNG compression '{}', expected fast, default or best",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for PngCompression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PngCompression::Fast => f.write_str("fast"),
+            PngCompression::Default => f.write_str("default"),
+            PngCompression::Best => f.write_str("best"),
+        }
+    }
+}
+
+/// Template for screenshot filenames, without the extension.
+///
+/// Supported tokens:
+///
+/// - `{date}`: local date, `2023-08-25`
+/// - `{time}`: local time, `18-02-47`
+/// - `{ms}`: milliseconds of the current second, `042`
+/// - `{screen}`: index of the captured screen, `all` for stitched screenshots
+/// - `{seq}`: sequence number, increased until the filename is unused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilenameTemplate(String);
+
+impl FilenameTemplate {
+    const TOKENS: [&'static str; 5] = ["date", "time", "ms", "screen", "seq"];
+
+    pub fn new(template: &str) -> Result<Self, String> {
+        if template.trim().is_empty() {
+            return Err("filename template is empty".to_string());
+        }
+
+        if template.contains(['/', '\\']) {
+            return Err(format!(
+                "filename template '{}' must not contain path separators",
+                template
+            ));
+        }
+
+        let mut rest = template;
+
+        while let Some(start) = rest.find('{') {
+            let Some(len) = rest[start..].find('}') else {
+                return Err(format!("unclosed '{{' in filename template '{}'", template));
+            };
+            let token = &rest[start + 1..start + len];
+
+            if !Self::TOKENS.contains(&token) {
+                return Err(format!(
+                    "unknown token '{{{}}}' in filename template, expected one of {}",
+                    token,
+                    Self::TOKENS
+                        .map(|token| format!("{{{}}}", token))
+                        .join(", ")
+                ));
+            }
+
+            rest = &rest[start + len + 1..];
+        }
+
+        Ok(FilenameTemplate(template.to_string()))
+    }
+
+    /// Render the filename, a `-<seq>` suffix is added if `seq > 0`
+    /// and the template has no `{seq}` token.
+    pub fn render(&self, time: &DateTime<Local>, screen: &str, seq: u32) -> String {
+        let mut filename = self
+            .0
+            .replace("{date}", &time.format("%Y-%m-%d").to_string())
+            .replace("{time}", &time.format("%H-%M-%S").to_string())
+            .replace(
+                "{ms}",
+                &format!("{:03}", time.timestamp_subsec_millis() % 1000),
+            )
+            .replace("{screen}", screen)
+            .replace("{seq}", &seq.to_string());
+
+        if seq > 0 && !self.0.contains("{seq}") {
+            filename += &format!("-{}", seq);
+        }
+
+        filename
+    }
+}
+
+impl Default for FilenameTemplate {
+    fn default() -> Self {
+        FilenameTemplate("{date}-{time}-{ms}".to_string())
+    }
+}
+
+impl fmt::Display for FilenameTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Encode with the png crate directly, `PngEncoder` can't write text chunks.
+fn encode_png<W>(
+    image: &RgbaImage,
+    writer: W,
+    compression: PngCompression,
+    text: &[(String, String)],
+) -> ImageResult<()>
+where
+    W: Write,
+{
+    let encoding_error = |err: png::EncodingError| match err {
+        png::EncodingError::IoError(err) => ImageError::IoError(err),
+        err => ImageError::Encoding(EncodingError::new(ImageFormat::Png.into(), err)),
+    };
+
+    let mut encoder = png::Encoder::new(writer, image.width(), image.height());
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_compression(compression.compression());
+    encoder.set_filter(png::FilterType::Sub);
+    encoder.set_adaptive_filter(png::AdaptiveFilterType::Adaptive);
+
+    for (keyword, text) in text {
+        // tEXt is Latin-1 only, iTXt takes the rest
+        let added = if text.is_ascii() {
+            encoder.add_text_chunk(keyword.clone(), text.clone())
+        } else {
+            encoder.add_itxt_chunk(keyword.clone(), text.clone())
+        };
+        added.map_err(encoding_error)?;
+    }
+
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(image.as_raw()))
+        .map_err(encoding_error)
+}
+
+/// Pick an unused path for a screenshot in `dir`.
+///
+/// Another capture may take it before it's written, use [`claim_path`] to
+/// reserve it.
+pub fn unique_path(
+    dir: &Path,
+    template: &FilenameTemplate,
+    time: &DateTime<Local>,
+    screen: &str,
+    format: OutputFormat,
+) -> PathBuf {
+    let mut seq = 0;
+
+    loop {
+        let filename = format!(
+            "{}.{}",
+            template.render(time, screen, seq),
+            format.extension()
+        );
+        let path = dir.join(filename);
+
+        if !path.exists() && !temp_path(&path).exists() {
+            return path;
+        }
+
+        seq += 1;
+    }
+}
+
+/// Reserve an unused path for a screenshot in `dir`, see [`unique_path`].
+///
+/// The path is claimed by creating its temporary file with `create_new`,
+/// which only one process can do, so concurrent captures never write to the
+/// same file. If it's taken, the next `seq` is tried.
+pub fn claim_path(
+    dir: &Path,
+    template: &FilenameTemplate,
+    time: &DateTime<Local>,
+    screen: &str,
+    format: OutputFormat,
+) -> Result<ClaimedPath, ScreenError> {
+    let mut seq = 0;
+
+    loop {
+        let filename = format!(
+            "{}.{}",
+            template.render(time, screen, seq),
+            format.extension()
+        );
+        let path = dir.join(filename);
+        let tmp_path = temp_path(&path);
+
+        seq += 1;
+
+        if path.exists() {
+            continue;
+        }
+
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&tmp_path)
+        {
+            Ok(_) => {}
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => {
+                return Err(ScreenError::Write {
+                    path,
+                    source: ImageError::IoError(err),
+                })
+            }
+        }
+
+        // Saved by another capture between the check and the claim
+        if path.exists() {
+            let _ = fs::remove_file(&tmp_path);
+            continue;
+        }
+
+        return Ok(ClaimedPath {
+            path,
+            tmp_path,
+            saved: false,
+        });
+    }
+}
+
+/// Screenshot path reserved by [`claim_path`].
+///
+/// The temporary file holding the claim is removed when it's dropped before
+/// the image is saved.
+#[derive(Debug)]
+pub struct ClaimedPath {
+    path: PathBuf,
+    tmp_path: PathBuf,
+    saved: bool,
+}
+
+impl ClaimedPath {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Encode `image` into the temporary file, see [`save_atomically`].
+    ///
+    /// Return how long encoding and writing the image took.
+    pub fn encode(
+        &self,
+        image: &RgbaImage,
+        format: OutputFormat,
+        compression: PngCompression,
+        text: &[(String, String)],
+    ) -> Result<Duration, ScreenError> {
+        encode_to_temp(image, &self.path, &self.tmp_path, format, compression, text)
+    }
+
+    /// Move the encoded image into place.
+    pub fn publish(mut self) -> Result<PathBuf, ScreenError> {
+        fs::rename(&self.tmp_path, &self.path).map_err(|err| ScreenError::Write {
+            path: self.path.clone(),
+            source: ImageError::IoError(err),
+        })?;
+        self.saved = true;
+
+        Ok(self.path.clone())
+    }
+}
+
+impl Drop for ClaimedPath {
+    fn drop(&mut self) {
+        if !self.saved {
+            let _ = fs::remove_file(&self.tmp_path);
+        }
+    }
+}
+
+/// Temporary path `image_path` is written to before it's renamed into place.
+///
+/// It's a hidden `.tmp` file in the same directory, queue consumers skip
+/// files starting with a dot.
+pub fn temp_path(image_path: &Path) -> PathBuf {
+    let filename = image_path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+
+    image_path.with_file_name(format!(".{}.tmp", filename))
+}
+
+/// Save `image` to `image_path` so that it only appears there once complete.
+///
+/// `text` is written into PNG text chunks as keyword and text pairs, other
+/// formats have no place for it.
+///
+/// Return how long encoding and writing the image took.
+pub fn save_atomically(
+    image: &RgbaImage,
+    image_path: &Path,
+    format: OutputFormat,
+    compression: PngCompression,
+    text: &[(String, String)],
+) -> Result<Duration, ScreenError> {
+    let tmp_path = temp_path(image_path);
+    let encode_time = encode_to_temp(image, image_path, &tmp_path, format, compression, text)?;
+
+    if let Err(err) = fs::rename(&tmp_path, image_path) {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(ScreenError::Write {
+            path: image_path.to_path_buf(),
+            source: ImageError::IoError(err),
+        });
+    }
+
+    Ok(encode_time)
+}
+
+/// Encode `image` into `tmp_path`, removing it again if that fails.
+fn encode_to_temp(
+    image: &RgbaImage,
+    image_path: &Path,
+    tmp_path: &Path,
+    format: OutputFormat,
+    compression: PngCompression,
+    text: &[(String, String)],
+) -> Result<Duration, ScreenError> {
+    let started = Instant::now();
+
+    if let Err(source) = encode(image, tmp_path, format, compression, text) {
+        let _ = fs::remove_file(tmp_path);
+        return Err(ScreenError::Write {
+            path: image_path.to_path_buf(),
+            source,
+        });
+    }
+
+    Ok(started.elapsed())
+}
+
+fn encode(
+    image: &RgbaImage,
+    path: &Path,
+    format: OutputFormat,
+    compression: PngCompression,
+    text: &[(String, String)],
+) -> ImageResult<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    let (width, height) = image.dimensions();
+    let buf = image.as_raw();
+
+    match format {
+        OutputFormat::Png => encode_png(image, &mut writer, compression, text),
+        OutputFormat::WebpLossless => {
+            WebPEncoder::new_lossless(&mut writer).write_image(buf, width, height, ColorType::Rgba8)
+        }
+        OutputFormat::Bmp => {
+            // 24-bit, OpenCV ignores the channel masks of 32-bit BMPs and swaps red and blue
+            let rgb: Vec<u8> = buf
+                .chunks_exact(4)
+                .flat_map(|pixel| &pixel[..3])
+                .copied()
+                .collect();
+            BmpEncoder::new(&mut writer).write_image(&rgb, width, height, ColorType::Rgb8)
+        }
+    }?;
+
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn time() -> DateTime<Local> {
+        Local
+            .with_ymd_and_hms(2023, 8, 25, 18, 2, 47)
+            .unwrap()
+            .checked_add_signed(chrono::Duration::milliseconds(42))
+            .unwrap()
+    }
+
+    #[test]
+    fn default_template_has_milliseconds() {
+        assert_eq!(
+            FilenameTemplate::default().render(&time(), "0", 0),
+            "2023-08-25-18-02-47-042"
+        );
+    }
+
+    #[test]
+    fn template_tokens() {
+        let template = FilenameTemplate::new("{date}_{time}_s{screen}_{seq}").unwrap();
+
+        assert_eq!(template.render(&time(), "1", 3), "2023-08-25_18-02-47_s1_3");
+    }
+
+    #[test]
+    fn invalid_templates() {
+        assert!(FilenameTemplate::new("").is_err());
+        assert!(FilenameTemplate::new("{date}-{nope}").is_err());
+        assert!(FilenameTemplate::new("{date").is_err());
+        assert!(FilenameTemplate::new("sub/{date}").is_err());
+    }
+
+    #[test]
+    fn unique_path_skips_existing_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let template = FilenameTemplate::default();
+
+        let first = unique_path(dir.path(), &template, &time(), "0", OutputFormat::Png);
+        fs::write(&first, b"").unwrap();
+        let second = unique_path(dir.path(), &template, &time(), "0", OutputFormat::Png);
+
+        assert_eq!(first.file_name().unwrap(), "2023-08-25-18-02-47-042.png");
+        assert_eq!(second.file_name().unwrap(), "2023-08-25-18-02-47-042-1.png");
+    }
+
+    #[test]
+    fn claimed_paths_are_not_claimed_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let template = FilenameTemplate::default();
+        let claim = || claim_path(dir.path(), &template, &time(), "0", OutputFormat::Png).unwrap();
+
+        let first = claim();
+        let second = claim();
+        assert_eq!(
+            first.path().file_name().unwrap(),
+            "2023-08-25-18-02-47-042.png"
+        );
+        assert_eq!(
+            second.path().file_name().unwrap(),
+            "2023-08-25-18-02-47-042-1.png"
+        );
+
+        first
+            .encode(
+                &RgbaImage::new(4, 4),
+                OutputFormat::Png,
+                PngCompression::default(),
+                &[],
+            )
+            .unwrap();
+        let saved = first.publish().unwrap();
+        drop(second);
+
+        let third = claim();
+        assert_eq!(
+            third.path(),
+            dir.path().join("2023-08-25-18-02-47-042-1.png")
+        );
+        drop(third);
+
+        let files: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(files, vec![saved]);
+    }
+
+    #[test]
+    fn temp_path_is_hidden_in_same_dir() {
+        let path = Path::new("data").join("queue").join("shot.png");
+
+        assert_eq!(
+            temp_path(&path),
+            Path::new("data").join("queue").join(".shot.png.tmp")
+        );
+    }
+
+    #[test]
+    fn save_leaves_no_temp_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("shot.png");
+
+        save_atomically(
+            &RgbaImage::new(4, 4),
+            &path,
+            OutputFormat::Png,
+            PngCompression::default(),
+            &[],
+        )
+        .unwrap();
+
+        let files: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(files, vec!["shot.png"]);
+        assert_eq!(image::open(&path).unwrap().width(), 4);
+    }
+
+    #[test]
+    fn failed_save_is_a_write_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("missing").join("shot.png");
+
+        assert!(matches!(
+            save_atomically(
+                &RgbaImage::new(4, 4),
+                &path,
+                OutputFormat::Png,
+                PngCompression::default(),
+                &[]
+            ),
+            Err(ScreenError::Write { .. })
+        ));
+    }
+
+    #[test]
+    fn png_compression_defaults_to_default() {
+        assert_eq!(PngCompression::default(), PngCompression::Default);
+        assert_eq!(PngCompression::default().to_string(), "default");
+    }
+
+    #[test]
+    fn all_formats_are_lossless() {
+        let dir = tempfile::tempdir().unwrap();
+        let image = RgbaImage::from_fn(16, 9, |x, y| {
+            image::Rgba([(x * 16) as u8, (y * 28) as u8, (x * y) as u8, 255])
+        });
+
+        for format in OutputFormat::ALL {
+            for compression in [
+                PngCompression::Fast,
+                PngCompression::Default,
+                PngCompression::Best,
+            ] {
+                let path = dir
+                    .path()
+                    .join(format!("{}.{}", compression, format.extension()));
+                save_atomically(&image, &path, format, compression, &[]).unwrap();
+
+                assert_eq!(image::open(&path).unwrap().to_rgba8(), image, "{}", format);
+            }
+        }
+    }
+
+    #[test]
+    fn every_format_has_a_matcher_fixture() {
+        // tests/test_matcher.py checks that the matcher reads these the same
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../tests/test_data/formats");
+        let png = image::open(dir.join("screenshot.png")).unwrap().to_rgba8();
+
+        for format in OutputFormat::ALL {
+            let path = dir.join(format!("screenshot.{}", format.extension()));
+
+            assert_eq!(image::open(&path).unwrap().to_rgba8(), png, "{}", format);
+        }
+    }
+}
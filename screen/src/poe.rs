@@ -0,0 +1,368 @@
+use crate::config::Config;
+use crate::error::ScreenError;
+use crate::normalize::{FULL_HD_HEIGHT, FULL_HD_WIDTH};
+use crate::region::Size;
+use crate::screens::ScreenInfo;
+use ini::Ini;
+use log::{debug, warn};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// cross-platform C:\Users\username\Documents\My Games\Path of Exile\production_Config.ini
+pub fn poe_config_path() -> Option<PathBuf> {
+    dirs_next::document_dir().map(|docs_dir| {
+        docs_dir
+            .join("My Games")
+            .join("Path of Exile")
+            .join("production_Config.ini")
+    })
+}
+
+/// Resolution the matcher's templates and thresholds are made for.
+pub const MATCHER_RESOLUTION: Size = Size {
+    width: FULL_HD_WIDTH,
+    height: FULL_HD_HEIGHT,
+};
+
+/// How PoE shows its window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowMode {
+    Fullscreen,
+    BorderlessFullscreen,
+    Windowed,
+}
+
+impl fmt::Display for WindowMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WindowMode::Fullscreen => f.write_str("fullscreen"),
+            WindowMode::BorderlessFullscreen => f.write_str("borderless windowed fullscreen"),
+            WindowMode::Windowed => f.write_str("windowed"),
+        }
+    }
+}
+
+/// Display settings from the `[DISPLAY]` section of PoE's `production_Config.ini`.
+///
+/// Settings that are missing or have a value PoE wouldn't write are `None`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PoeConfig {
+    /// Graphics adapter and monitor, e.g. `AMD Radeon RX 5700 XT(#0)`.
+    pub adapter_name: Option<String>,
+    /// `resolution_width` and `resolution_height`.
+    pub resolution: Option<Size>,
+    /// From `fullscreen` and `borderless_windowed_fullscreen`.
+    pub window_mode: Option<WindowMode>,
+}
+
+impl PoeConfig {
+    /// Read PoE's config, `Ok(None)` if it doesn't exist.
+    ///
+    /// It's an error if the file exists but isn't an INI file, invalid values
+    /// are logged and skipped.
+    pub fn load<P>(path: P) -> Result<Option<Self>, ScreenError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+
+        if !path.exists() {
+            debug!("PoE config path doesn't exist (are you on Linux?)");
+            return Ok(None);
+        }
+
+        let ini_file = Ini::load_from_file(path).map_err(|err| ScreenError::PoeConfig {
+            path: path.to_path_buf(),
+            reason: err.to_string(),
+        })?;
+
+        Ok(Some(Self::from_ini(&ini_file)))
+    }
+
+    fn from_ini(ini_file: &Ini) -> Self {
+        let get = |key| ini_file.get_from(Some("DISPLAY"), key).map(str::trim);
+        let number = |key| {
+            let value = get(key)?;
+            let number = value.parse::<u32>().ok().filter(|number| *number > 0);
+
+            if number.is_none() {
+                warn!("Invalid {} '{}' in PoE's config", key, value);
+            }
+
+            number
+        };
+        let flag = |key| match get(key)?.to_ascii_lowercase().as_str() {
+            "true" | "1" => Some(true),
+            "false" | "0" => Some(false),
+            value => {
+                warn!("Invalid {} '{}' in PoE's config", key, value);
+                None
+            }
+        };
+
+        let resolution = match (number("resolution_width"), number("resolution_height")) {
+            (Some(width), Some(height)) => Some(Size { width, height }),
+            _ => None,
+        };
+        let window_mode = match (flag("fullscreen"), flag("borderless_windowed_fullscreen")) {
+            (Some(true), _) => Some(WindowMode::Fullscreen),
+            (_, Some(true)) => Some(WindowMode::BorderlessFullscreen),
+            (None, None) => None,
+            _ => Some(WindowMode::Windowed),
+        };
+
+        PoeConfig {
+            adapter_name: get("adapter_name").map(str::to_string),
+            resolution,
+            window_mode,
+        }
+    }
+
+    /// Index of the monitor PoE runs on, from the `(#N)` suffix of the
+    /// adapter name, the first one if PoE doesn't name one.
+    pub fn monitor(&self) -> Option<usize> {
+        monitor_index_from_adapter_name(self.adapter_name.as_deref().unwrap_or("(#0)"))
+    }
+
+    /// Describe the settings that hurt matching or don't fit `screen`, the
+    /// screen PoE runs on.
+    ///
+    /// Problems `config` already works around aren't reported.
+    pub fn mismatches(&self, screen: Option<&ScreenInfo>, config: &Config) -> Vec<String> {
+        let mut mismatches = Vec::new();
+        let Some(resolution) = self.resolution else {
+            return mismatches;
+        };
+
+        if resolution != MATCHER_RESOLUTION && !config.normalize {
+            mismatches.push(format!(
+                "PoE runs at {}, the matcher is made for {}, enable normalize to rescale screenshots",
+                resolution, MATCHER_RESOLUTION
+            ));
+        }
+
+        let Some(screen) = screen else {
+            return mismatches;
+        };
+        let screen_size = physical_size(screen);
+
+        match self.window_mode {
+            Some(WindowMode::BorderlessFullscreen) if resolution != screen_size => {
+                mismatches.push(format!(
+                    "PoE is set to {} in {} mode, but screen {} is {}",
+                    resolution,
+                    WindowMode::BorderlessFullscreen,
+                    screen.index,
+                    screen_size
+                ))
+            }
+            Some(WindowMode::Windowed)
+                if !config.poe_region
+                    && config.region.is_none()
+                    && config.cursor_window.is_none()
+                    && (resolution.width < screen_size.width
+                        || resolution.height < screen_size.height) =>
+            {
+                mismatches.push(format!(
+                    "PoE runs {} at {} on screen {} ({}), enable poe_region to capture only the game",
+                    WindowMode::Windowed,
+                    resolution,
+                    screen.index,
+                    screen_size
+                ))
+            }
+            _ => {}
+        }
+
+        mismatches
+    }
+}
+
+/// Size of `screen` in physical pixels.
+fn physical_size(screen: &ScreenInfo) -> Size {
+    Size {
+        width: (screen.width as f32 * screen.scale_factor).round() as u32,
+        height: (screen.height as f32 * screen.scale_factor).round() as u32,
+    }
+}
+
+/// Read poe production_Config.ini, try to find the index of the preferred minitor.
+/// Something like this:
+///
+///  adapter_name=AMD Radeon RX 5700 XT(#0)
+///
+/// Return `Ok(None)` if the config doesn't exist or doesn't name a monitor,
+/// and an error if it exists but can't be parsed.
+pub fn active_monitor_number_from_poe_config<P>(
+    poe_config_path: P,
+) -> Result<Option<usize>, ScreenError>
+where
+    P: AsRef<Path>,
+{
+    Ok(PoeConfig::load(poe_config_path)?.and_then(|poe_config| poe_config.monitor()))
+}
+
+fn monitor_index_from_adapter_name(adapter_name: &str) -> Option<usize> {
+    let start = adapter_name.rfind("(#")?;
+    let end = adapter_name.rfind(')')?;
+    let substr = adapter_name.get(start + 2..end)?;
+
+    substr.parse::<usize>().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn write_poe_config(contents: &str) -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("production_Config.ini");
+        fs::write(&path, contents).unwrap();
+
+        (dir, path)
+    }
+
+    #[test]
+    fn monitor_from_adapter_name() {
+        let (_dir, path) = write_poe_config("[DISPLAY]\nadapter_name=AMD Radeon RX 5700 XT(#1)\n");
+
+        assert_eq!(
+            active_monitor_number_from_poe_config(&path).unwrap(),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn adapter_name_without_index() {
+        let (_dir, path) = write_poe_config("[DISPLAY]\nadapter_name=AMD Radeon RX 5700 XT\n");
+
+        assert_eq!(active_monitor_number_from_poe_config(&path).unwrap(), None);
+    }
+
+    #[test]
+    fn corrupt_poe_config_is_an_error() {
+        let (_dir, path) = write_poe_config("[DISPLAY\nadapter_name=(#1)\n");
+
+        assert!(matches!(
+            active_monitor_number_from_poe_config(&path),
+            Err(ScreenError::PoeConfig { .. })
+        ));
+    }
+
+    #[test]
+    fn missing_poe_config() {
+        let dir = tempfile::tempdir().unwrap();
+
+        assert_eq!(
+            active_monitor_number_from_poe_config(dir.path().join("production_Config.ini"))
+                .unwrap(),
+            None
+        );
+    }
+
+    fn screen(width: u32, height: u32) -> ScreenInfo {
+        ScreenInfo {
+            index: 0,
+            id: 1,
+            name: None,
+            x: 0,
+            y: 0,
+            width,
+            height,
+            scale_factor: 1.0,
+            is_primary: true,
+            poe_detected: true,
+        }
+    }
+
+    fn windowed(width: u32, height: u32) -> PoeConfig {
+        PoeConfig {
+            adapter_name: None,
+            resolution: Some(Size { width, height }),
+            window_mode: Some(WindowMode::Windowed),
+        }
+    }
+
+    #[test]
+    fn parse_display_settings() {
+        let (_dir, path) = write_poe_config(
+            "[DISPLAY]\nadapter_name=NVIDIA GeForce RTX 3070(#1)\nresolution_width=2560\n\
+             resolution_height=1440\nfullscreen=false\nborderless_windowed_fullscreen=true\n",
+        );
+        let poe_config = PoeConfig::load(&path).unwrap().unwrap();
+
+        assert_eq!(poe_config.monitor(), Some(1));
+        assert_eq!(
+            poe_config.resolution,
+            Some(Size {
+                width: 2560,
+                height: 1440
+            })
+        );
+        assert_eq!(
+            poe_config.window_mode,
+            Some(WindowMode::BorderlessFullscreen)
+        );
+    }
+
+    #[test]
+    fn invalid_values_are_skipped() {
+        let (_dir, path) = write_poe_config(
+            "[DISPLAY]\nresolution_width=wide\nresolution_height=1080\nfullscreen=maybe\n",
+        );
+        let poe_config = PoeConfig::load(&path).unwrap().unwrap();
+
+        assert_eq!(poe_config.resolution, None);
+        assert_eq!(poe_config.window_mode, None);
+        assert_eq!(poe_config.monitor(), Some(0));
+    }
+
+    #[test]
+    fn reports_mismatches() {
+        let config = Config::default();
+        let borderless = PoeConfig {
+            window_mode: Some(WindowMode::BorderlessFullscreen),
+            ..windowed(1920, 1080)
+        };
+
+        assert!(borderless
+            .mismatches(Some(&screen(1920, 1080)), &config)
+            .is_empty());
+        assert_eq!(
+            borderless
+                .mismatches(Some(&screen(2560, 1440)), &config)
+                .len(),
+            1
+        );
+        assert_eq!(windowed(1280, 720).mismatches(None, &config).len(), 1);
+
+        let normalized = Config {
+            normalize: true,
+            ..Config::default()
+        };
+        assert!(windowed(1280, 720).mismatches(None, &normalized).is_empty());
+
+        let cropped = Config {
+            region: "320,180,1920,1080".parse().ok(),
+            ..Config::default()
+        };
+        assert_eq!(
+            windowed(1920, 1080)
+                .mismatches(Some(&screen(2560, 1440)), &config)
+                .len(),
+            1
+        );
+        assert!(windowed(1920, 1080)
+            .mismatches(Some(&screen(2560, 1440)), &cropped)
+            .is_empty());
+
+        let poe_region = Config {
+            poe_region: true,
+            ..Config::default()
+        };
+        assert!(windowed(1920, 1080)
+            .mismatches(Some(&screen(2560, 1440)), &poe_region)
+            .is_empty());
+    }
+}
@@ -0,0 +1,184 @@
+use serde::Serialize;
+use std::fmt;
+use std::str::FromStr;
+
+/// Rectangle on a screen, relative to its top left corner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Region {
+    /// Window of `size` centered on `center`, moved and shrunk as needed to
+    /// stay within a `width`x`height` screen.
+    pub fn around(center: (i32, i32), size: Size, width: u32, height: u32) -> Self {
+        let (x, width) = center_span(center.0, size.width, width);
+        let (y, height) = center_span(center.1, size.height, height);
+
+        Region {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Whether the region lies completely within a `width`x`height` screen.
+    pub fn fits(&self, width: u32, height: u32) -> bool {
+        self.x
+            .checked_add(self.width)
+            .is_some_and(|right| right <= width)
+            && self
+                .y
+                .checked_add(self.height)
+                .is_some_and(|bottom| bottom <= height)
+    }
+}
+
+/// Parse `x,y,w,h`.
+impl FromStr for Region {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("expected region as x,y,width,height, got '{}'", s);
+        let values = s
+            .split(',')
+            .map(|value| value.trim().parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| invalid())?;
+
+        let [x, y, width, height] = values[..] else {
+            return Err(invalid());
+        };
+
+        if width == 0 || height == 0 {
+            return Err(format!("region '{}' is empty", s));
+        }
+
+        Ok(Region {
+            x,
+            y,
+            width,
+            height,
+        })
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{},{},{}", self.x, self.y, self.width, self.height)
+    }
+}
+
+/// Width and height of a capture window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Size {
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Parse `WxH`.
+impl FromStr for Size {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("expected size as WIDTHxHEIGHT, got '{}'", s);
+        let (width, height) = s.split_once(['x', 'X']).ok_or_else(invalid)?;
+        let width = width.trim().parse::<u32>().map_err(|_| invalid())?;
+        let height = height.trim().parse::<u32>().map_err(|_| invalid())?;
+
+        if width == 0 || height == 0 {
+            return Err(format!("size '{}' is empty", s));
+        }
+
+        Ok(Size { width, height })
+    }
+}
+
+impl fmt::Display for Size {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
+
+/// Start and length of a `size` long span centered on `center`, within `0..bound`.
+fn center_span(center: i32, size: u32, bound: u32) -> (u32, u32) {
+    let size = size.min(bound);
+    let start = i64::from(center) - i64::from(size / 2);
+    let start = start.clamp(0, i64::from(bound - size));
+
+    (start as u32, size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_region() {
+        assert_eq!(
+            " 960, 0,960 ,1080".parse::<Region>(),
+            Ok(Region {
+                x: 960,
+                y: 0,
+                width: 960,
+                height: 1080
+            })
+        );
+        assert!("1,2,3".parse::<Region>().is_err());
+        assert!("-1,0,10,10".parse::<Region>().is_err());
+        assert!("0,0,0,10".parse::<Region>().is_err());
+    }
+
+    #[test]
+    fn parse_size() {
+        assert_eq!(
+            "800x600".parse::<Size>(),
+            Ok(Size {
+                width: 800,
+                height: 600
+            })
+        );
+        assert!("800".parse::<Size>().is_err());
+        assert!("0x600".parse::<Size>().is_err());
+    }
+
+    #[test]
+    fn window_around_cursor_is_centered() {
+        let size = "800x600".parse().unwrap();
+
+        assert_eq!(
+            Region::around((1000, 500), size, 1920, 1080),
+            "600,200,800,600".parse().unwrap()
+        );
+    }
+
+    #[test]
+    fn window_around_cursor_is_clamped_to_screen() {
+        let size = "800x600".parse().unwrap();
+
+        assert_eq!(
+            Region::around((1900, 10), size, 1920, 1080),
+            "1120,0,800,600".parse().unwrap()
+        );
+        assert_eq!(
+            Region::around((-50, 2000), size, 1920, 1080),
+            "0,480,800,600".parse().unwrap()
+        );
+        assert_eq!(
+            Region::around((100, 100), size, 640, 480),
+            "0,0,640,480".parse().unwrap()
+        );
+    }
+
+    #[test]
+    fn region_fits_screen() {
+        let region: Region = "960,0,960,1080".parse().unwrap();
+
+        assert!(region.fits(1920, 1080));
+        assert!(!region.fits(1919, 1080));
+    }
+}
@@ -0,0 +1,341 @@
+use crate::error::ScreenError;
+use screenshots::display_info::DisplayInfo;
+use screenshots::Screen;
+use serde::Serialize;
+use std::fmt;
+use std::str::FromStr;
+
+/// Description of one display, in the order of `Screen::all()`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ScreenInfo {
+    /// Index usable as `screen` in `config.ini`.
+    pub index: usize,
+    /// Display id reported by the OS.
+    pub id: u32,
+    /// Device name, `\\.\DISPLAY1` on Windows, the output name (`HDMI-1`) on X11.
+    pub name: Option<String>,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub scale_factor: f32,
+    pub is_primary: bool,
+    /// Whether `screen = -1` would pick this screen based on the PoE config.
+    pub poe_detected: bool,
+}
+
+impl ScreenInfo {
+    pub(crate) fn new(index: usize, info: &DisplayInfo, poe_detected: bool) -> Self {
+        ScreenInfo {
+            index,
+            id: info.id,
+            name: display_name(info),
+            x: info.x,
+            y: info.y,
+            width: info.width,
+            height: info.height,
+            scale_factor: info.scale_factor,
+            is_primary: info.is_primary,
+            poe_detected,
+        }
+    }
+
+    fn contains(&self, x: i32, y: i32) -> bool {
+        let (x, y) = (i64::from(x), i64::from(y));
+        let (left, top) = (i64::from(self.x), i64::from(self.y));
+
+        (left..left + i64::from(self.width)).contains(&x)
+            && (top..top + i64::from(self.height)).contains(&y)
+    }
+}
+
+impl fmt::Display for ScreenInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Screen {}: display {}", self.index, self.id)?;
+
+        if let Some(name) = &self.name {
+            write!(f, " ({})", name)?;
+        }
+
+        write!(
+            f,
+            ", {}x{}px at ({}, {}), scale {}{}{}",
+            self.width,
+            self.height,
+            self.x,
+            self.y,
+            self.scale_factor,
+            if self.is_primary { ", primary" } else { "" },
+            if self.poe_detected { " <- PoE" } else { "" },
+        )
+    }
+}
+
+/// Which screen to capture, the `screen` value in `config.ini`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScreenSelector {
+    /// `-1` or `auto`, the monitor PoE runs on according to its config.
+    Auto,
+    /// Index into `Screen::all()`, which changes when monitors are re-plugged.
+    Index(usize),
+    /// `id:<id>`, the display id reported by the OS.
+    Id(u32),
+    /// `name:<name>`, the device name of the display.
+    Name(String),
+    /// `point:<x>,<y>`, the display containing a point in desktop coordinates.
+    ContainsPoint(i32, i32),
+    /// `cursor`, the display under the mouse pointer at capture time.
+    Cursor,
+    /// `all`, every display.
+    All,
+}
+
+impl ScreenSelector {
+    /// Find the screen matching a stable identity in `screens`.
+    ///
+    /// Return `None` for the selectors that aren't matched against the
+    /// screens, `Auto`, `Index`, `Cursor` and `All`.
+    pub fn find<'a>(&self, screens: &'a [ScreenInfo]) -> Option<&'a ScreenInfo> {
+        match self {
+            ScreenSelector::Auto
+            | ScreenSelector::Index(_)
+            | ScreenSelector::Cursor
+            | ScreenSelector::All => None,
+            ScreenSelector::Id(id) => screens.iter().find(|info| info.id == *id),
+            ScreenSelector::Name(name) => screens.iter().find(|info| {
+                info.name
+                    .as_deref()
+                    .is_some_and(|other| device_name(other).eq_ignore_ascii_case(device_name(name)))
+            }),
+            ScreenSelector::ContainsPoint(x, y) => {
+                screens.iter().find(|info| info.contains(*x, *y))
+            }
+        }
+    }
+}
+
+impl Default for ScreenSelector {
+    fn default() -> Self {
+        ScreenSelector::Index(0)
+    }
+}
+
+impl FromStr for ScreenSelector {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        if s == "-1" || s.eq_ignore_ascii_case("auto") {
+            return Ok(ScreenSelector::Auto);
+        }
+
+        if s.eq_ignore_ascii_case("cursor") {
+            return Ok(ScreenSelector::Cursor);
+        }
+
+        if s.eq_ignore_ascii_case("all") {
+            return Ok(ScreenSelector::All);
+        }
+
+        if let Ok(index) = s.parse::<usize>() {
+            return Ok(ScreenSelector::Index(index));
+        }
+
+        let invalid = || {
+            format!(
+                "expected -1, auto, cursor, all, a screen index, id:<id>, name:<name> or point:<x>,<y>, got '{}'",
+                s
+            )
+        };
+        let (kind, value) = s.split_once(':').ok_or_else(invalid)?;
+        let value = value.trim();
+
+        match kind.trim().to_ascii_lowercase().as_str() {
+            "id" => value.parse().map(ScreenSelector::Id).map_err(|_| invalid()),
+            "name" if !value.is_empty() => Ok(ScreenSelector::Name(value.to_string())),
+            "point" => {
+                let (x, y) = value.split_once(',').ok_or_else(invalid)?;
+                let x = x.trim().parse().map_err(|_| invalid())?;
+                let y = y.trim().parse().map_err(|_| invalid())?;
+
+                Ok(ScreenSelector::ContainsPoint(x, y))
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for ScreenSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScreenSelector::Auto => write!(f, "auto"),
+            ScreenSelector::Index(index) => write!(f, "{}", index),
+            ScreenSelector::Id(id) => write!(f, "id:{}", id),
+            ScreenSelector::Name(name) => write!(f, "name:{}", name),
+            ScreenSelector::ContainsPoint(x, y) => write!(f, "point:{},{}", x, y),
+            ScreenSelector::Cursor => write!(f, "cursor"),
+            ScreenSelector::All => write!(f, "all"),
+        }
+    }
+}
+
+/// Drop the `\\.\` prefix of Windows device names, so `DISPLAY1` matches too.
+fn device_name(name: &str) -> &str {
+    name.strip_prefix(r"\\.\").unwrap_or(name)
+}
+
+/// Get all screens, failing if there are none.
+pub fn all_screens() -> Result<Vec<Screen>, ScreenError> {
+    let screens = Screen::all().map_err(|err| ScreenError::Capture(err.to_string()))?;
+
+    if screens.is_empty() {
+        return Err(ScreenError::NoDisplays);
+    }
+
+    Ok(screens)
+}
+
+/// Describe all screens, marking `poe_screen` as the one PoE runs on.
+pub fn list_screens(poe_screen: Option<usize>) -> Result<Vec<ScreenInfo>, ScreenError> {
+    let screens = all_screens()?;
+
+    Ok(screens
+        .iter()
+        .enumerate()
+        .map(|(index, screen)| {
+            ScreenInfo::new(index, &screen.display_info, poe_screen == Some(index))
+        })
+        .collect())
+}
+
+/// Device name of a display, if the platform has one.
+#[cfg(target_os = "windows")]
+fn display_name(info: &DisplayInfo) -> Option<String> {
+    use windows::Win32::Graphics::Gdi::{GetMonitorInfoW, MONITORINFO, MONITORINFOEXW};
+
+    let mut monitor_info = MONITORINFOEXW::default();
+    monitor_info.monitorInfo.cbSize = std::mem::size_of::<MONITORINFOEXW>() as u32;
+
+    // Safe, MONITORINFOEXW starts with MONITORINFO and cbSize tells its real size
+    let found = unsafe {
+        GetMonitorInfoW(
+            info.raw_handle,
+            &mut monitor_info as *mut MONITORINFOEXW as *mut MONITORINFO,
+        )
+    };
+
+    if !found.as_bool() {
+        return None;
+    }
+
+    let device = &monitor_info.szDevice;
+    let len = device.iter().position(|&c| c == 0).unwrap_or(device.len());
+
+    Some(String::from_utf16_lossy(&device[..len]))
+}
+
+/// Device name of a display, if the platform has one.
+#[cfg(target_os = "linux")]
+fn display_name(info: &DisplayInfo) -> Option<String> {
+    let (conn, _) =
+        xcb::Connection::connect_with_extensions(None, &[xcb::Extension::RandR], &[]).ok()?;
+    let cookie = conn.send_request(&xcb::randr::GetOutputInfo {
+        output: info.raw_handle,
+        config_timestamp: 0,
+    });
+    let reply = conn.wait_for_reply(cookie).ok()?;
+
+    Some(String::from_utf8_lossy(reply.name()).into_owned())
+}
+
+/// Device name of a display, if the platform has one.
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+fn display_name(_info: &DisplayInfo) -> Option<String> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn screen(index: usize, id: u32, name: &str, x: i32) -> ScreenInfo {
+        ScreenInfo {
+            index,
+            id,
+            name: Some(name.to_string()),
+            x,
+            y: 0,
+            width: 1920,
+            height: 1080,
+            scale_factor: 1.0,
+            is_primary: index == 0,
+            poe_detected: false,
+        }
+    }
+
+    #[test]
+    fn parse_selector() {
+        let parse = |s: &str| s.parse::<ScreenSelector>();
+
+        assert_eq!(parse("-1"), Ok(ScreenSelector::Auto));
+        assert_eq!(parse("Auto"), Ok(ScreenSelector::Auto));
+        assert_eq!(parse("2"), Ok(ScreenSelector::Index(2)));
+        assert_eq!(parse("cursor"), Ok(ScreenSelector::Cursor));
+        assert_eq!(parse("ALL"), Ok(ScreenSelector::All));
+        assert_eq!(parse("id:65539"), Ok(ScreenSelector::Id(65539)));
+        assert_eq!(
+            parse(r"name:\\.\DISPLAY2"),
+            Ok(ScreenSelector::Name(r"\\.\DISPLAY2".to_string()))
+        );
+        assert_eq!(
+            parse("point: -100, 50"),
+            Ok(ScreenSelector::ContainsPoint(-100, 50))
+        );
+        assert!(parse("-2").is_err());
+        assert!(parse("name:").is_err());
+        assert!(parse("point:1").is_err());
+        assert!(parse("primary").is_err());
+    }
+
+    #[test]
+    fn selector_display_round_trips() {
+        for selector in [
+            "auto",
+            "1",
+            "id:7",
+            "name:HDMI-1",
+            "point:-10,20",
+            "cursor",
+            "all",
+        ] {
+            assert_eq!(
+                selector.parse::<ScreenSelector>().unwrap().to_string(),
+                selector
+            );
+        }
+    }
+
+    #[test]
+    fn find_screen_by_identity() {
+        let screens = [
+            screen(0, 65537, r"\\.\DISPLAY1", 0),
+            screen(1, 65539, r"\\.\DISPLAY2", -1920),
+        ];
+        let find = |s: &str| {
+            s.parse::<ScreenSelector>()
+                .unwrap()
+                .find(&screens)
+                .map(|info| info.index)
+        };
+
+        assert_eq!(find("id:65539"), Some(1));
+        assert_eq!(find(r"name:\\.\DISPLAY1"), Some(0));
+        assert_eq!(find("name:display2"), Some(1));
+        assert_eq!(find("point:-1,1079"), Some(1));
+        assert_eq!(find("point:1919,0"), Some(0));
+        assert_eq!(find("point:1920,0"), None);
+        assert_eq!(find("id:1"), None);
+        assert_eq!(find("0"), None);
+    }
+}
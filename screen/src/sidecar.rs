@@ -0,0 +1,177 @@
+//! `<name>.json` saved next to a screenshot, describing how it was captured.
+
+use crate::capture::{Capture, ScreenChoice};
+use crate::error::ScreenError;
+use crate::output::temp_path;
+use crate::region::Region;
+use crate::screens::{ScreenInfo, ScreenSelector};
+use chrono::SecondsFormat;
+use image::ImageError;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Version of the capturer, recorded in every sidecar.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Debug, Serialize)]
+struct Sidecar<'a> {
+    /// Capture time in RFC 3339 with the local offset.
+    timestamp: String,
+    version: &'static str,
+    image: String,
+    /// The `screen` setting the screen was resolved from.
+    selector: String,
+    chosen_by: ScreenChoice,
+    /// Tag of the capture session, e.g. the league.
+    session: Option<&'a str>,
+    screen: Option<&'a ScreenInfo>,
+    region: Option<Region>,
+    cursor: Option<Point>,
+    original_size: Dimensions,
+    size: Dimensions,
+    scale: f32,
+    capture_ms: f64,
+    encode_ms: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+#[derive(Debug, Serialize)]
+struct Dimensions {
+    width: u32,
+    height: u32,
+}
+
+impl From<(u32, u32)> for Dimensions {
+    fn from((width, height): (u32, u32)) -> Self {
+        Dimensions { width, height }
+    }
+}
+
+/// Path of the sidecar belonging to `image_path`.
+pub fn sidecar_path(image_path: &Path) -> PathBuf {
+    image_path.with_extension("json")
+}
+
+/// Save the sidecar of `capture` next to its screenshot.
+pub fn write_sidecar(
+    capture: &Capture,
+    selector: &ScreenSelector,
+    session: Option<&str>,
+) -> Result<(), ScreenError> {
+    let sidecar = Sidecar {
+        timestamp: capture.time.to_rfc3339_opts(SecondsFormat::Millis, false),
+        version: VERSION,
+        image: capture
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        selector: selector.to_string(),
+        chosen_by: capture.chosen_by,
+        session,
+        screen: capture.screen.as_ref(),
+        region: capture.region,
+        cursor: capture.cursor.map(|(x, y)| Point { x, y }),
+        original_size: capture.original_size.into(),
+        size: capture.size.into(),
+        scale: capture.scale,
+        capture_ms: millis(capture.capture_time),
+        encode_ms: millis(capture.encode_time),
+    };
+
+    let path = sidecar_path(&capture.path);
+    let tmp_path = temp_path(&path);
+    let json = serde_json::to_string_pretty(&sidecar).expect("sidecar is serializable");
+
+    fs::write(&tmp_path, json)
+        .and_then(|_| fs::rename(&tmp_path, &path))
+        .map_err(|err| {
+            let _ = fs::remove_file(&tmp_path);
+
+            ScreenError::Write {
+                path,
+                source: ImageError::IoError(err),
+            }
+        })
+}
+
+fn millis(duration: Duration) -> f64 {
+    (duration.as_secs_f64() * 1000.0 * 1000.0).round() / 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Local, TimeZone};
+
+    #[test]
+    fn sidecar_describes_the_capture() {
+        let dir = tempfile::tempdir().unwrap();
+        let capture = Capture {
+            path: dir.path().join("shot.png"),
+            time: Local.with_ymd_and_hms(2023, 8, 25, 18, 2, 47).unwrap(),
+            screen: None,
+            chosen_by: ScreenChoice::PoeConfig,
+            region: None,
+            cursor: Some((-10, 20)),
+            original_size: (2560, 1440),
+            size: (1920, 1080),
+            scale: 0.75,
+            capture_time: Duration::from_micros(12_345),
+            encode_time: Duration::from_millis(80),
+        };
+
+        write_sidecar(&capture, &ScreenSelector::Auto, Some("settlers")).unwrap();
+
+        let json = fs::read_to_string(dir.path().join("shot.json")).unwrap();
+        let sidecar: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        assert!(sidecar["timestamp"]
+            .as_str()
+            .unwrap()
+            .starts_with("2023-08-25T18:02:47.000"));
+        assert_eq!(sidecar["image"], "shot.png");
+        assert_eq!(sidecar["selector"], "auto");
+        assert_eq!(sidecar["chosen_by"], "poe-config");
+        assert_eq!(sidecar["session"], "settlers");
+        assert_eq!(sidecar["cursor"]["x"], -10);
+        assert_eq!(sidecar["original_size"]["width"], 2560);
+        assert_eq!(sidecar["scale"], 0.75);
+        assert_eq!(sidecar["capture_ms"], 12.345);
+        assert_eq!(sidecar["version"], VERSION);
+    }
+
+    #[test]
+    fn sidecar_records_cursor_of_screen_capture() {
+        let dir = tempfile::tempdir().unwrap();
+        let capture = Capture {
+            path: dir.path().join("shot.png"),
+            time: Local.with_ymd_and_hms(2023, 8, 25, 18, 2, 47).unwrap(),
+            screen: None,
+            chosen_by: ScreenChoice::Index,
+            region: None,
+            cursor: Some((640, 360)),
+            original_size: (1920, 1080),
+            size: (1920, 1080),
+            scale: 1.0,
+            capture_time: Duration::from_millis(10),
+            encode_time: Duration::from_millis(80),
+        };
+
+        write_sidecar(&capture, &ScreenSelector::Index(0), None).unwrap();
+
+        let json = fs::read_to_string(dir.path().join("shot.json")).unwrap();
+        let sidecar: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(sidecar["chosen_by"], "index");
+        assert_eq!(sidecar["cursor"]["x"], 640);
+        assert_eq!(sidecar["cursor"]["y"], 360);
+    }
+}
@@ -0,0 +1,208 @@
+//! Locating unique item tooltips by their title bar decorations.
+//!
+//! Mirrors `Matcher._find_unique_control_start` and `_find_unique_control_end`
+//! from the Python matcher, using the same templates and threshold.
+
+use crate::error::ScreenError;
+use crate::matching::{screenshot_to_gray, to_gray, PreparedImage};
+use image::{imageops, DynamicImage, GrayImage, RgbaImage};
+use std::path::{Path, PathBuf};
+
+/// Default maximum score of a title bar decoration match, `THRESHOLD_CONTROL` in the matcher.
+pub const THRESHOLD_CONTROL: f32 = 0.16;
+
+/// Size of the item art left of the title bar, `ITEM_MAX_SIZE` in the matcher.
+const ITEM_MAX_SIZE: (u32, u32) = (104, 208);
+
+/// Template file name and the file compiled into the binary.
+type TemplateFile = (&'static str, &'static [u8]);
+
+const ONE_LINE: TemplateFile = (
+    "unique-one-line-fullhd.png",
+    include_bytes!("../../templates/unique-one-line-fullhd.png"),
+);
+const ONE_LINE_END: TemplateFile = (
+    "unique-one-line-end-fullhd.png",
+    include_bytes!("../../templates/unique-one-line-end-fullhd.png"),
+);
+const TWO_LINE: TemplateFile = (
+    "unique-two-line-fullhd.png",
+    include_bytes!("../../templates/unique-two-line-fullhd.png"),
+);
+const TWO_LINE_END: TemplateFile = (
+    "unique-two-line-end-fullhd.png",
+    include_bytes!("../../templates/unique-two-line-end-fullhd.png"),
+);
+const TWO_LINE_CMP: TemplateFile = (
+    "unique-two-line-fullhd-compressed.png",
+    include_bytes!("../../templates/unique-two-line-fullhd-compressed.png"),
+);
+const TWO_LINE_END_CMP: TemplateFile = (
+    "unique-two-line-end-fullhd-compressed.png",
+    include_bytes!("../../templates/unique-two-line-end-fullhd-compressed.png"),
+);
+
+/// Grayscale title bar decorations of unique items (1920x1080).
+pub struct TitleTemplates {
+    one_line: GrayImage,
+    one_line_end: GrayImage,
+    two_line: GrayImage,
+    two_line_end: GrayImage,
+    two_line_cmp: GrayImage,
+    two_line_end_cmp: GrayImage,
+}
+
+impl TitleTemplates {
+    /// Load the templates from `dir` if given, otherwise use the embedded ones.
+    pub fn new(dir: Option<&Path>) -> Result<Self, ScreenError> {
+        match dir {
+            Some(dir) => Self::load(dir),
+            None => Self::embedded(),
+        }
+    }
+
+    /// Use the templates compiled into the binary.
+    pub fn embedded() -> Result<Self, ScreenError> {
+        Self::build(|(name, bytes)| {
+            image::load_from_memory(bytes).map_err(|source| ScreenError::Templates {
+                path: PathBuf::from(name),
+                source,
+            })
+        })
+    }
+
+    /// Load the `unique-*-fullhd.png` templates from `dir`.
+    pub fn load(dir: &Path) -> Result<Self, ScreenError> {
+        Self::build(|(name, _)| {
+            let path = dir.join(name);
+
+            image::open(&path).map_err(|source| ScreenError::Templates { path, source })
+        })
+    }
+
+    fn build<F>(load: F) -> Result<Self, ScreenError>
+    where
+        F: Fn(TemplateFile) -> Result<DynamicImage, ScreenError>,
+    {
+        let load = |file| load(file).map(|image| to_gray(&image.to_rgba8()));
+
+        Ok(TitleTemplates {
+            one_line: load(ONE_LINE)?,
+            one_line_end: load(ONE_LINE_END)?,
+            two_line: load(TWO_LINE)?,
+            two_line_end: load(TWO_LINE_END)?,
+            two_line_cmp: load(TWO_LINE_CMP)?,
+            two_line_end_cmp: load(TWO_LINE_END_CMP)?,
+        })
+    }
+}
+
+/// Position of a unique item's title bar in a screenshot.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TitleBar {
+    /// Top left corner of the start decoration.
+    pub start: (u32, u32),
+    /// Top left corner of the end decoration.
+    pub end: (u32, u32),
+    /// Identified items have a two-line title.
+    pub identified: bool,
+    /// Score of the start decoration match.
+    pub min_val: f32,
+    /// Size of the start and end decorations.
+    start_size: (u32, u32),
+    end_size: (u32, u32),
+}
+
+/// Find the title bar of a unique item in `screenshot`.
+///
+/// Decorations only count as found if their score is at most `threshold`.
+pub fn find_title_bar(
+    screenshot: &RgbaImage,
+    templates: &TitleTemplates,
+    threshold: f32,
+) -> Option<TitleBar> {
+    let gray = screenshot_to_gray(screenshot);
+    let prepared = PreparedImage::new(&gray);
+
+    let candidates = [
+        (&templates.one_line, false),
+        (&templates.two_line, true),
+        (&templates.two_line_cmp, true),
+    ];
+
+    let (start, start_template, identified) =
+        candidates.iter().find_map(|(template, identified)| {
+            prepared
+                .find(template)
+                .filter(|found| found.min_val <= threshold)
+                .map(|found| (found, *template, *identified))
+        })?;
+
+    // The end decoration is on the same line, right of the start
+    let (x, y) = start.min_loc;
+    let strip_height = (start_template.height() * 2).min(gray.height() - y);
+    let strip = imageops::crop_imm(&gray, x, y, gray.width() - x, strip_height).to_image();
+    let strip = PreparedImage::new(&strip);
+
+    let end_templates: &[&GrayImage] = if identified {
+        &[&templates.two_line_end, &templates.two_line_end_cmp]
+    } else {
+        &[&templates.one_line_end]
+    };
+
+    let (end, end_template) = end_templates.iter().find_map(|template| {
+        strip
+            .find(template)
+            .filter(|found| found.min_val <= threshold)
+            .map(|found| (found, *template))
+    })?;
+
+    Some(TitleBar {
+        start: start.min_loc,
+        end: (x + end.min_loc.0, y + end.min_loc.1),
+        identified,
+        min_val: start.min_val,
+        start_size: start_template.dimensions(),
+        end_size: end_template.dimensions(),
+    })
+}
+
+/// Crop `screenshot` to the title bar and the item art left of it, plus `margin` pixels.
+///
+/// This is the part of the tooltip the matcher reads.
+pub fn crop_to_title_bar(screenshot: &RgbaImage, title_bar: &TitleBar, margin: u32) -> RgbaImage {
+    let left = title_bar.start.0.saturating_sub(ITEM_MAX_SIZE.0 + margin);
+    let top = title_bar.start.1.saturating_sub(margin);
+    let right = (title_bar.end.0 + title_bar.end_size.0 + margin).min(screenshot.width());
+    let bottom = (title_bar.start.1 + ITEM_MAX_SIZE.1.max(title_bar.start_size.1) + margin)
+        .min(screenshot.height());
+
+    imageops::crop_imm(screenshot, left, top, right - left, bottom - top).to_image()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn embedded_templates_match_files() {
+        let embedded = TitleTemplates::embedded().unwrap();
+        let loaded =
+            TitleTemplates::load(&Path::new(env!("CARGO_MANIFEST_DIR")).join("../templates"))
+                .unwrap();
+
+        assert_eq!(embedded.one_line.dimensions(), (31, 37));
+        assert_eq!(embedded.two_line_end_cmp.dimensions(), (46, 58));
+        assert_eq!(embedded.two_line, loaded.two_line);
+    }
+
+    #[test]
+    fn missing_template_dir() {
+        let dir = tempfile::tempdir().unwrap();
+
+        assert!(matches!(
+            TitleTemplates::load(dir.path()),
+            Err(ScreenError::Templates { .. })
+        ));
+    }
+}
@@ -0,0 +1,238 @@
+//! Finding PoE's window on the desktop, in the same coordinates as the
+//! mouse pointer and the screen positions.
+
+use crate::error::ScreenError;
+use crate::region::Region;
+use crate::screens::ScreenInfo;
+
+/// Client area of a window in desktop coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowRect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl WindowRect {
+    /// Part of `screen` the window covers, `None` if it's on another screen.
+    pub fn region_on(&self, screen: &ScreenInfo) -> Option<Region> {
+        let left = self.x.max(screen.x);
+        let top = self.y.max(screen.y);
+        let right = (self.x + self.width as i32).min(screen.x + screen.width as i32);
+        let bottom = (self.y + self.height as i32).min(screen.y + screen.height as i32);
+
+        if right <= left || bottom <= top {
+            return None;
+        }
+
+        Some(Region {
+            x: (left - screen.x) as u32,
+            y: (top - screen.y) as u32,
+            width: (right - left) as u32,
+            height: (bottom - top) as u32,
+        })
+    }
+}
+
+/// Client area of PoE's window, `None` if the game isn't running or is
+/// minimized.
+#[cfg(target_os = "windows")]
+pub fn poe_window() -> Result<Option<WindowRect>, ScreenError> {
+    use windows::core::{w, PCWSTR};
+    use windows::Win32::Foundation::{POINT, RECT};
+    use windows::Win32::Graphics::Gdi::ClientToScreen;
+    use windows::Win32::UI::WindowsAndMessaging::{FindWindowW, GetClientRect, IsIconic};
+
+    // Safe, the pointers are valid for the duration of the calls
+    unsafe {
+        let window = FindWindowW(w!("POEWindowClass"), PCWSTR::null());
+
+        if window.0 == 0 || IsIconic(window).as_bool() {
+            return Ok(None);
+        }
+
+        let mut rect = RECT::default();
+        GetClientRect(window, &mut rect).map_err(|err| ScreenError::Window(err.to_string()))?;
+
+        let mut origin = POINT::default();
+        if !ClientToScreen(window, &mut origin).as_bool() {
+            return Err(ScreenError::Window(
+                "cannot map the window position to the desktop".to_string(),
+            ));
+        }
+
+        Ok(Some(WindowRect {
+            x: origin.x,
+            y: origin.y,
+            width: (rect.right - rect.left) as u32,
+            height: (rect.bottom - rect.top) as u32,
+        }))
+    }
+}
+
+/// Client area of PoE's window, `None` if the game isn't running or is
+/// minimized.
+#[cfg(target_os = "linux")]
+pub fn poe_window() -> Result<Option<WindowRect>, ScreenError> {
+    use crate::cursor::to_logical;
+    use xcb::x;
+
+    let window_error = |err: String| ScreenError::Window(err);
+    let (conn, index) =
+        xcb::Connection::connect(None).map_err(|err| window_error(err.to_string()))?;
+    let root = conn
+        .get_setup()
+        .roots()
+        .nth(index as usize)
+        .ok_or_else(|| window_error("X server has no screens".to_string()))?
+        .root();
+
+    let atom = |name: &str| -> Result<x::Atom, ScreenError> {
+        let cookie = conn.send_request(&x::InternAtom {
+            only_if_exists: false,
+            name: name.as_bytes(),
+        });
+        Ok(conn
+            .wait_for_reply(cookie)
+            .map_err(|err| window_error(err.to_string()))?
+            .atom())
+    };
+    let property = |window: x::Window, property: x::Atom, r#type: x::Atom| {
+        let cookie = conn.send_request(&x::GetProperty {
+            delete: false,
+            window,
+            property,
+            r#type,
+            long_offset: 0,
+            long_length: 4096,
+        });
+        conn.wait_for_reply(cookie)
+            .map_err(|err| window_error(err.to_string()))
+    };
+
+    let client_list = atom("_NET_CLIENT_LIST")?;
+    let net_wm_name = atom("_NET_WM_NAME")?;
+    let utf8_string = atom("UTF8_STRING")?;
+
+    // Managed windows, as the window manager lists them for taskbars
+    let clients = property(root, client_list, x::ATOM_WINDOW)?;
+    let clients = match clients.format() {
+        32 => clients.value::<x::Window>().to_vec(),
+        _ => Vec::new(),
+    };
+
+    for window in clients {
+        let name = property(window, net_wm_name, utf8_string)?;
+        let class = property(window, x::ATOM_WM_CLASS, x::ATOM_STRING)?;
+        let text = |reply: &x::GetPropertyReply| match reply.format() {
+            8 => String::from_utf8_lossy(reply.value::<u8>()).to_lowercase(),
+            _ => String::new(),
+        };
+
+        // The title is the same everywhere, the class depends on how it's
+        // started, `pathofexile_x64steam.exe` through Steam's Proton
+        if text(&name) != "path of exile" && !text(&class).contains("pathofexile") {
+            continue;
+        }
+
+        let cookie = conn.send_request(&x::GetGeometry {
+            drawable: x::Drawable::Window(window),
+        });
+        let geometry = conn
+            .wait_for_reply(cookie)
+            .map_err(|err| window_error(err.to_string()))?;
+        let cookie = conn.send_request(&x::TranslateCoordinates {
+            src_window: window,
+            dst_window: root,
+            src_x: 0,
+            src_y: 0,
+        });
+        let origin = conn
+            .wait_for_reply(cookie)
+            .map_err(|err| window_error(err.to_string()))?;
+
+        // Physical pixels like the pointer, see `cursor_position`
+        let scale_factor = crate::screens::all_screens()?[0].display_info.scale_factor;
+        let (x, y) = to_logical(
+            (i32::from(origin.dst_x()), i32::from(origin.dst_y())),
+            scale_factor,
+        );
+        let (right, bottom) = to_logical(
+            (
+                i32::from(origin.dst_x()) + i32::from(geometry.width()),
+                i32::from(origin.dst_y()) + i32::from(geometry.height()),
+            ),
+            scale_factor,
+        );
+
+        return Ok(Some(WindowRect {
+            x,
+            y,
+            width: (right - x) as u32,
+            height: (bottom - y) as u32,
+        }));
+    }
+
+    Ok(None)
+}
+
+/// Client area of PoE's window, PoE doesn't run natively on macOS.
+#[cfg(target_os = "macos")]
+pub fn poe_window() -> Result<Option<WindowRect>, ScreenError> {
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn screen(x: i32, y: i32, width: u32, height: u32) -> ScreenInfo {
+        ScreenInfo {
+            index: 0,
+            id: 0,
+            name: None,
+            x,
+            y,
+            width,
+            height,
+            scale_factor: 1.0,
+            is_primary: true,
+            poe_detected: false,
+        }
+    }
+
+    #[test]
+    fn window_region_on_screens() {
+        let window = WindowRect {
+            x: 2240,
+            y: 180,
+            width: 1280,
+            height: 720,
+        };
+
+        // Completely on the right screen
+        assert_eq!(
+            window.region_on(&screen(1920, 0, 2560, 1440)),
+            Some(Region {
+                x: 320,
+                y: 180,
+                width: 1280,
+                height: 720
+            })
+        );
+        assert_eq!(window.region_on(&screen(0, 0, 1920, 1080)), None);
+
+        // Partly moved off the left screen
+        let straddling = WindowRect { x: 1600, ..window };
+        assert_eq!(
+            straddling.region_on(&screen(0, 0, 1920, 1080)),
+            Some(Region {
+                x: 1600,
+                y: 180,
+                width: 320,
+                height: 720
+            })
+        );
+    }
+}
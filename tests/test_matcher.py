@@ -65,3 +65,13 @@ def test_get_base_name(base, screenshots, matcher):
     for screenshot in screenshots:
         cropped_item = matcher.find_unique(screenshot)
         assert cropped_item.base == base
+
+
+@pytest.mark.parametrize("extension", ["png", "webp", "bmp"])
+def test_load_screen_reads_every_output_format(extension, matcher):
+    """Test that screenshots saved by `screen` in any of its formats load the same."""
+    screen = matcher.load_screen(DATA_DIR / "formats" / f"screenshot.{extension}")
+    expected = matcher.load_screen(DATA_DIR / "formats" / "screenshot.png")
+
+    assert screen.shape == expected.shape
+    assert (screen == expected).all()
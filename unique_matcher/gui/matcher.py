@@ -1,5 +1,6 @@
 import os
 import shutil
+from pathlib import Path
 
 from loguru import logger
 from PySide6.QtCore import Property, QObject, QTimer, Signal, Slot
@@ -42,20 +43,46 @@ class QmlMatcher(QObject):
         """Return the number of items in the DB."""
         return len(self.matcher.item_loader.items)
 
+    def _screenshots(self, directory: Path) -> list[str]:
+        """Return screenshots in a data directory.
+
+        Hidden files are skipped, the screenshot tool writes into hidden
+        temporary files and renames them once complete. JSON sidecars with
+        capture metadata are skipped too.
+        """
+        return [
+            file
+            for file in os.listdir(directory)
+            if not file.startswith(".") and not file.endswith(".json")
+        ]
+
+    def _queue_files(self) -> list[str]:
+        """Return screenshots in the queue."""
+        return self._screenshots(QUEUE_DIR)
+
+    def _move(self, file: str, directory: Path) -> None:
+        """Move a screenshot out of the queue, along with its sidecar."""
+        shutil.move(QUEUE_DIR / file, directory / file)
+
+        sidecar = (QUEUE_DIR / file).with_suffix(".json")
+
+        if sidecar.exists():
+            shutil.move(sidecar, directory / sidecar.name)
+
     @Property(int, notify=queue_length_changed)
     def queue_length(self) -> int:
         """Return the size of the queue."""
-        return len(os.listdir(QUEUE_DIR))
+        return len(self._queue_files())
 
     @Property(int, notify=processed_length_changed)
     def processed_length(self) -> int:
         """Return the number of processed screenshots."""
-        return len(os.listdir(DONE_DIR))
+        return len(self._screenshots(DONE_DIR))
 
     @Property(int, notify=errors_length_changed)
     def errors_length(self) -> int:
         """Return the number of errors."""
-        return len(os.listdir(ERROR_DIR))
+        return len(self._screenshots(ERROR_DIR))
 
     @Slot()
     def process_next(self) -> None:
@@ -63,7 +90,7 @@ class QmlMatcher(QObject):
         if self.queue_length == 0:
             return
 
-        file = os.listdir(QUEUE_DIR)[0]
+        file = self._queue_files()[0]
 
         try:
             result = self.matcher.find_item(QUEUE_DIR / file)
@@ -81,7 +108,7 @@ class QmlMatcher(QObject):
             )
             self._cnt += 1
 
-            shutil.move(QUEUE_DIR / file, DONE_DIR / file)
+            self._move(file, DONE_DIR)
             self.processed_length_changed.emit()
         except BaseUMError as e:
             self.newResult.emit(
@@ -94,7 +121,7 @@ class QmlMatcher(QObject):
             )
             self._cnt += 1
 
-            shutil.move(QUEUE_DIR / file, ERROR_DIR / file)
+            self._move(file, ERROR_DIR)
             self.errors_length_changed.emit()
             logger.exception("Error during processing: {}", str(e))
 